@@ -0,0 +1,357 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File};
+use std::io::{BufReader, Cursor, Read};
+use std::os::unix::fs::symlink;
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::Config;
+use crate::downloader;
+use crate::manifest::ArchiveLayout;
+use crate::state::InstalledTool;
+
+/// Unpack a verified archive into the package prefix and link its executables
+///
+/// Returns every link and directory the package now owns. Anything `current`
+/// (the installed record of the tool, if any) owns that the new version no
+/// longer provides is removed. Other files in the way are only replaced with `force`.
+pub fn install_package(
+    config: &Config,
+    tool_name: &str,
+    archive_path: &Path,
+    layout: &ArchiveLayout,
+    current: Option<&InstalledTool>,
+    force: bool,
+) -> Result<Vec<PathBuf>> {
+    let prefix = config.package_dir(tool_name);
+    let share_dir = config.share_dir();
+    let old_files = current.map(|c| c.files.as_slice()).unwrap_or_default();
+
+    // Never clobber executables that belong to something else or were put there by hand
+    for bin in &layout.bin {
+        let link = link_path(config, bin)?;
+        let ours = old_files.contains(&link)
+            || (link == config.install_dir.join(tool_name)
+                && current.is_some_and(|c| c.archive.is_none()));
+        if !ours && !force && fs::symlink_metadata(&link).is_ok() {
+            anyhow::bail!(
+                "{:?} already exists and is not part of {} (use --force to replace it)",
+                link,
+                tool_name
+            );
+        }
+    }
+    fs::create_dir_all(&share_dir)
+        .context(format!("Failed to create share directory: {:?}", share_dir))?;
+
+    // Unpack beside the live prefix so the previous version stays usable until the swap
+    let staging = share_dir.join(format!(".{}.gpm-new", tool_name));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+
+    if let Err(e) = unpack(archive_path, layout, &staging) {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+
+    for bin in &layout.bin {
+        if !staging.join(bin).is_file() {
+            let _ = fs::remove_dir_all(&staging);
+            anyhow::bail!("Archive does not contain executable '{}'", bin);
+        }
+    }
+
+    let retired = share_dir.join(format!(".{}.gpm-old", tool_name));
+    if prefix.exists() {
+        if retired.exists() {
+            fs::remove_dir_all(&retired)?;
+        }
+        fs::rename(&prefix, &retired).context("Failed to retire previous package files")?;
+    }
+    fs::rename(&staging, &prefix).context("Failed to move package into place")?;
+    if retired.exists() {
+        fs::remove_dir_all(&retired)?;
+    }
+
+    let mut files = vec![prefix.clone()];
+
+    for bin in &layout.bin {
+        let link = link_path(config, bin)?;
+        replace_symlink(&prefix.join(bin), &link)?;
+        files.push(link);
+    }
+
+    // Drop links the previous version had but this one does not
+    remove_files_except(old_files, &files)?;
+
+    Ok(files)
+}
+
+/// Delete every link and directory recorded for a package
+pub fn remove_files(files: &[PathBuf]) -> Result<()> {
+    remove_files_except(files, &[])
+}
+
+/// Delete recorded package files, sparing any that are still in use
+pub fn remove_files_except(files: &[PathBuf], keep: &[PathBuf]) -> Result<()> {
+    for path in files {
+        if !keep.contains(path) && fs::symlink_metadata(path).is_ok() {
+            remove_path(path).context(format!("Failed to remove {:?}", path))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn remove_path(path: &Path) -> Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+
+    if metadata.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+/// Executables are linked into the install dir under their file name
+fn link_path(config: &Config, bin: &str) -> Result<PathBuf> {
+    let name = Path::new(bin)
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Invalid executable path in archive: '{}'", bin))?;
+
+    Ok(config.install_dir.join(name))
+}
+
+fn replace_symlink(target: &Path, link: &Path) -> Result<()> {
+    let name = link
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let temp_link = link.with_file_name(format!(".{}.gpm-link", name));
+
+    if fs::symlink_metadata(&temp_link).is_ok() {
+        fs::remove_file(&temp_link)?;
+    }
+    symlink(target, &temp_link).context(format!("Failed to link {:?}", link))?;
+    fs::rename(&temp_link, link).context(format!("Failed to link {:?}", link))?;
+
+    Ok(())
+}
+
+fn unpack(archive_path: &Path, layout: &ArchiveLayout, dest: &Path) -> Result<()> {
+    let file = File::open(archive_path).context("Failed to open downloaded archive")?;
+    let reader = decoder(&layout.format, file)?;
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
+
+    for entry in archive.entries().context("Failed to read archive")? {
+        let mut entry = entry.context("Failed to read archive entry")?;
+        let path = normalize(&entry.path()?);
+
+        if !is_selected(&path, layout) {
+            continue;
+        }
+
+        // unpack_in refuses entries that would escape `dest`
+        entry
+            .unpack_in(dest)
+            .context(format!("Failed to unpack {:?}", path))?;
+    }
+
+    Ok(())
+}
+
+/// Path, sha256 and size of every regular file `layout` unpacks from the archive
+pub fn checksums(
+    archive_path: &Path,
+    layout: &ArchiveLayout,
+) -> Result<Vec<(PathBuf, String, u64)>> {
+    let file = File::open(archive_path).context("Failed to open cached archive")?;
+    let reader = decoder(&layout.format, file)?;
+    let mut archive = tar::Archive::new(reader);
+    let mut files = Vec::new();
+
+    for entry in archive.entries().context("Failed to read archive")? {
+        let entry = entry.context("Failed to read archive entry")?;
+        let path = normalize(&entry.path()?);
+
+        if !entry.header().entry_type().is_file() || !is_selected(&path, layout) {
+            continue;
+        }
+
+        let size = entry.size();
+        let sha256 = downloader::sha256_reader(entry)
+            .context(format!("Failed to read {:?} from archive", path))?;
+        files.push((path, sha256, size));
+    }
+
+    Ok(files)
+}
+
+fn decoder(format: &str, file: File) -> Result<Box<dyn Read>> {
+    let reader: Box<dyn Read> = match format {
+        "tar.gz" | "tgz" => Box::new(flate2::read::GzDecoder::new(file)),
+        "tar.xz" | "txz" => {
+            // lzma-rs has no streaming reader, so decompress into memory
+            let mut data = Vec::new();
+            lzma_rs::xz_decompress(&mut BufReader::new(file), &mut data)
+                .map_err(|e| anyhow::anyhow!("Failed to decompress xz archive: {:?}", e))?;
+            Box::new(Cursor::new(data))
+        }
+        "tar.zst" | "tzst" => Box::new(
+            ruzstd::decoding::StreamingDecoder::new(file)
+                .map_err(|e| anyhow::anyhow!("Failed to decompress zstd archive: {}", e))?,
+        ),
+        other => anyhow::bail!("Unsupported archive format '{}'", other),
+    };
+
+    Ok(reader)
+}
+
+/// Strip `./` prefixes so manifest paths and archive paths compare equal
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+fn is_selected(path: &Path, layout: &ArchiveLayout) -> bool {
+    if layout.data.is_empty() {
+        return true;
+    }
+
+    let in_bin = layout
+        .bin
+        .iter()
+        .any(|bin| normalize(Path::new(bin)) == path);
+    let in_data = layout
+        .data
+        .iter()
+        .any(|dir| path.starts_with(normalize(Path::new(dir))));
+
+    in_bin || in_data
+}
+
+/// A package whose only file is the executable `bin/pk`, written to `dir`
+#[cfg(test)]
+pub fn package_for_tests(dir: &Path) -> PathBuf {
+    let path = dir.join("pk.tar.gz");
+    let encoder =
+        flate2::write::GzEncoder::new(File::create(&path).unwrap(), flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    let contents = b"#!/bin/sh\necho package\n";
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o755);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, "bin/pk", &contents[..])
+        .unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout() -> ArchiveLayout {
+        ArchiveLayout {
+            format: "tar.gz".to_string(),
+            bin: vec!["bin/pk".to_string()],
+            data: Vec::new(),
+        }
+    }
+
+    fn record(archive: Option<ArchiveLayout>, files: Vec<PathBuf>) -> InstalledTool {
+        InstalledTool {
+            version: "1.0".to_string(),
+            sha256: "0".repeat(64),
+            url: "https://example.com/pk".to_string(),
+            installed_at: 0,
+            repo: "test".to_string(),
+            repo_version: "1.0.0".to_string(),
+            size: None,
+            archive,
+            files,
+            channel: None,
+            depends: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn refuses_to_replace_an_untracked_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(dir.path());
+        let archive = package_for_tests(dir.path());
+        let loose = config.install_dir.join("pk");
+        fs::write(&loose, "mine").unwrap();
+
+        let error = install_package(&config, "pk", &archive, &layout(), None, false).unwrap_err();
+        assert!(error.to_string().contains("--force"), "{}", error);
+        assert_eq!(fs::read_to_string(&loose).unwrap(), "mine");
+        assert!(!config.package_dir("pk").exists());
+    }
+
+    #[test]
+    fn force_replaces_an_untracked_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(dir.path());
+        let archive = package_for_tests(dir.path());
+        let link = config.install_dir.join("pk");
+        fs::write(&link, "mine").unwrap();
+
+        let files = install_package(&config, "pk", &archive, &layout(), None, true).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(files, vec![config.package_dir("pk"), link]);
+    }
+
+    #[test]
+    fn replaces_the_tracked_binary_of_the_same_tool() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(dir.path());
+        let archive = package_for_tests(dir.path());
+        let link = config.install_dir.join("pk");
+        fs::write(&link, "old build").unwrap();
+
+        let current = record(None, Vec::new());
+        install_package(&config, "pk", &archive, &layout(), Some(&current), false).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+    }
+
+    #[test]
+    fn upgrades_its_own_links_and_drops_stale_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(dir.path());
+        let archive = package_for_tests(dir.path());
+
+        let first = install_package(&config, "pk", &archive, &layout(), None, false).unwrap();
+        let stale = config.install_dir.join("pk-old");
+        symlink(config.package_dir("pk").join("bin/pk"), &stale).unwrap();
+        let mut files = first.clone();
+        files.push(stale.clone());
+
+        let current = record(Some(layout()), files);
+        let second =
+            install_package(&config, "pk", &archive, &layout(), Some(&current), false).unwrap();
+        assert_eq!(second, first);
+        assert!(fs::symlink_metadata(&stale).is_err());
+    }
+
+    #[test]
+    fn refuses_a_file_another_record_does_not_own() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(dir.path());
+        let archive = package_for_tests(dir.path());
+        fs::write(config.install_dir.join("pk"), "someone else").unwrap();
+
+        // A package record for a different layout does not own the binary path
+        let current = record(Some(layout()), vec![config.package_dir("pk")]);
+        assert!(
+            install_package(&config, "pk", &archive, &layout(), Some(&current), false).is_err()
+        );
+    }
+}
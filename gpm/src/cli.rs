@@ -1,4 +1,8 @@
 use clap::{Parser, Subcommand};
+use std::collections::BTreeMap;
+
+use crate::config::Settings;
+use crate::output::OutputFormat;
 
 #[derive(Parser)]
 #[command(name = "gpm")]
@@ -8,25 +12,84 @@ pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
 
-    /// Installation directory for binaries
-    #[arg(long, global = true, default_value = "/DATA/AppData/glochidia/bin")]
-    pub install_dir: String,
+    /// Installation directory for binaries [default: /DATA/AppData/glochidia/bin]
+    #[arg(long, global = true)]
+    pub install_dir: Option<String>,
 
-    /// Cache directory for manifest and temporary files
-    #[arg(long, global = true, default_value = "/DATA/AppData/glochidia/.cache")]
-    pub cache_dir: String,
+    /// Cache directory for manifest and temporary files [default: /DATA/AppData/glochidia/.cache]
+    #[arg(long, global = true)]
+    pub cache_dir: Option<String>,
 
     /// Manifest URL
-    #[arg(
-        long,
-        global = true,
-        default_value = "https://github.com/uairhahs/glochidia/releases/download/latest/manifest.json"
-    )]
-    pub manifest_url: String,
+    #[arg(long, global = true)]
+    pub manifest_url: Option<String>,
 
     /// Enable verbose output
     #[arg(short, long, global = true)]
     pub verbose: bool,
+
+    /// Number of downloads to run in parallel [default: 4]
+    #[arg(short, long, global = true)]
+    pub jobs: Option<usize>,
+
+    /// HTTP(S) proxy to use for all requests
+    #[arg(long, global = true)]
+    pub proxy: Option<String>,
+
+    /// Network timeout in seconds, 0 for none [default: 300]
+    #[arg(long, global = true)]
+    pub timeout: Option<u64>,
+
+    /// Wait for another running gpm to finish instead of failing
+    #[arg(long, global = true, overrides_with = "no_wait")]
+    pub wait: bool,
+
+    /// Fail immediately if another gpm is running (default)
+    #[arg(long, global = true, overrides_with = "wait")]
+    pub no_wait: bool,
+
+    /// Accept unsigned or badly signed manifests (not recommended)
+    #[arg(long, global = true)]
+    pub insecure: bool,
+
+    /// Use only cached manifests and artifacts and local repositories; never touch the network
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    /// Accept expired manifests and ones older than already seen (for recovery only)
+    #[arg(long, global = true)]
+    pub allow_stale_manifest: bool,
+
+    /// Print results as text, or as JSON or TSV for scripts (messages go to stderr)
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+}
+
+impl Cli {
+    /// Settings given on the command line, which override every other layer
+    pub fn settings(&self) -> Settings {
+        Settings {
+            install_dir: self.install_dir.clone(),
+            cache_dir: self.cache_dir.clone(),
+            manifest_url: self.manifest_url.clone(),
+            proxy: self.proxy.clone(),
+            timeout: self.timeout,
+            jobs: self.jobs,
+            require_signature: self.insecure.then_some(false),
+            keep_versions: None,
+            wait: match (self.wait, self.no_wait) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            cache_ttl: None,
+            mirrors: None,
+            mirror_strategy: None,
+            offline: self.offline.then_some(true),
+            channel: None,
+            repos: BTreeMap::new(),
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -39,12 +102,49 @@ pub enum Commands {
         /// Install all available tools
         #[arg(short, long)]
         all: bool,
+
+        /// Overwrite files in the install dir that gpm did not install
+        #[arg(short, long)]
+        force: bool,
+
+        /// Follow this release channel (e.g. testing) for these tools from now on
+        #[arg(long)]
+        channel: Option<String>,
+
+        /// Install only required dependencies, not recommended ones
+        #[arg(long)]
+        no_recommends: bool,
+    },
+    /// Start tracking a file in the install dir that matches a repository tool by sha256
+    Adopt {
+        /// File name in the install dir, or a path to it
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        file: Option<String>,
+
+        /// Adopt every untracked file that matches a repository tool
+        #[arg(long)]
+        all: bool,
+
+        /// Rename matches to their tool's name, and adopt files matching no build by name
+        #[arg(short, long)]
+        force: bool,
     },
     /// List installed tools
     List,
+    /// Show everything known about a tool, installed or not
+    Info {
+        /// Name of the tool, or 'repo/name'
+        tool_name: String,
+    },
     /// List available tools from manifest
     #[command(name = "list-remote")]
     ListRemote,
+    /// Search tool names, descriptions and licenses
+    Search {
+        /// Words to look for; every word has to match
+        #[arg(required = true)]
+        query: Vec<String>,
+    },
     /// Remove one or more installed tools
     #[command(alias = "uninstall", alias = "rm")]
     Remove {
@@ -54,15 +154,167 @@ pub enum Commands {
         /// Remove all installed tools
         #[arg(short, long)]
         all: bool,
+
+        /// Also remove files in the install dir that gpm did not install
+        #[arg(short, long)]
+        force: bool,
     },
     /// Update manifest cache
     Update,
 
+    /// Show installed tools with a newer version available
+    Outdated {
+        /// Names or patterns of installed tools to check (supports wildcards)
+        tool_names: Vec<String>,
+    },
+    /// Upgrade installed tools without installing new ones
+    Upgrade {
+        /// Names or patterns of installed tools to upgrade (supports wildcards like 'g*' or '*')
+        tool_names: Vec<String>,
+
+        /// Upgrade all installed tools
+        #[arg(short, long)]
+        all: bool,
+    },
+    /// Restore the previously installed version of a tool
+    Rollback {
+        /// Name of the tool to roll back
+        tool_name: String,
+    },
+    /// Keep a tool at its installed version, or at `tool@version`; lists pins without arguments
+    Pin {
+        /// Tool to pin, as 'name' or 'name@version'
+        tool: Option<String>,
+    },
+    /// Let install and upgrade move a pinned tool again
+    Unpin {
+        /// Name of the pinned tool
+        tool_name: String,
+    },
+    /// Check installed files against the checksums they were installed with
+    Verify {
+        /// Names or patterns of tools to check (all when omitted)
+        tool_names: Vec<String>,
+
+        /// Reinstall the recorded build of every missing, corrupted or modified tool
+        #[arg(long)]
+        repair: bool,
+    },
+
+    /// Export tools and a rewritten manifest into a self-contained repository directory
+    Mirror {
+        /// Directory to write the snapshot into
+        dir: String,
+
+        /// Names or patterns of tools to export (all tools when omitted)
+        tool_names: Vec<String>,
+
+        /// Only export builds for this target triple (repeatable; default: every target)
+        #[arg(long = "target")]
+        targets: Vec<String>,
+
+        /// Minisign secret key to sign the exported manifest with
+        #[arg(long)]
+        sign_key: Option<String>,
+
+        /// Export only required dependencies, not recommended ones
+        #[arg(long)]
+        no_recommends: bool,
+    },
+
+    /// Show or change persistent settings in gpm.toml
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Manage the repositories tools are installed from
+    Repo {
+        #[command(subcommand)]
+        action: RepoAction,
+    },
+
     /// Configure shell PATH for installed binaries
     #[command(name = "setup-path")]
     SetupPath,
 }
 
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Print the effective value of a setting
+    Get {
+        /// Setting name, e.g. install_dir
+        key: String,
+    },
+    /// Store a setting in the user (or system) gpm.toml
+    Set {
+        /// Setting name, e.g. install_dir
+        key: String,
+
+        /// New value
+        value: String,
+
+        /// Write the system-wide file instead of the user one
+        #[arg(long)]
+        system: bool,
+    },
+    /// Remove a setting from the user (or system) gpm.toml
+    Unset {
+        /// Setting name, e.g. install_dir
+        key: String,
+
+        /// Write the system-wide file instead of the user one
+        #[arg(long)]
+        system: bool,
+    },
+    /// Show every setting and where its value comes from
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum RepoAction {
+    /// Add a repository, or replace one with the same name
+    Add {
+        /// Repository name, used as 'gpm install <name>/<tool>'
+        name: String,
+
+        /// Manifest URL
+        url: String,
+
+        /// Repositories with a higher priority win when several provide a tool
+        #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+        priority: i64,
+
+        /// Minisign public key (base64, or path to a .pub file) that must sign this manifest
+        #[arg(long)]
+        key: Option<String>,
+
+        /// Mirror base URL tried before the manifest's own URLs (repeatable)
+        #[arg(long = "mirror")]
+        mirrors: Vec<String>,
+
+        /// Release channel its tools follow (default: stable)
+        #[arg(long)]
+        channel: Option<String>,
+
+        /// Write the system-wide file instead of the user one
+        #[arg(long)]
+        system: bool,
+    },
+    /// Remove a repository
+    #[command(alias = "rm")]
+    Remove {
+        /// Repository name
+        name: String,
+
+        /// Write the system-wide file instead of the user one
+        #[arg(long)]
+        system: bool,
+    },
+    /// List repositories in resolution order
+    List,
+}
+
 pub fn parse() -> Cli {
     Cli::parse()
 }
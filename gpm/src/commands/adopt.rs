@@ -0,0 +1,449 @@
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::commands::install;
+use crate::config::Config;
+use crate::downloader;
+use crate::manifest::{self, Artifact, Catalog, RepoManifest, Tool};
+use crate::output::{fail, say, Kind};
+use crate::state::{self, InstalledDb, InstalledTool};
+
+/// Version recorded for a file adopted by name alone, older than any real release
+const UNKNOWN_VERSION: &str = "unknown";
+
+/// The repository tool an untracked file is taken to be
+struct Match<'a> {
+    name: &'a str,
+    release: Cow<'a, Tool>,
+    artifact: Artifact,
+    repo: &'a RepoManifest,
+    /// Whether the file is that exact build, rather than a tool of the same name
+    exact: bool,
+}
+
+/// Start tracking a file in the install dir that matches a manifest entry by sha256
+///
+/// A file under another name than the tool it matches is only renamed to the
+/// tool's name with `force`. With `force`, a file that matches no build is also
+/// adopted as an unknown version of the tool it is named after, so the next
+/// upgrade replaces it.
+pub fn run(config: &Config, file: Option<&str>, all: bool, force: bool) -> Result<()> {
+    let mut db = InstalledDb::load(config)?;
+    if all {
+        return run_all(config, &mut db, force);
+    }
+    let Some(file) = file else {
+        fail!(Kind::Usage, "Name a file to adopt, or use --all");
+    };
+
+    let path = locate(config, file)?;
+    let file_name = file_name(&path);
+    if db.get(&file_name).is_some() || db.owns_file(&path) {
+        say!("{} is already managed by gpm", file_name);
+        return Ok(());
+    }
+
+    let sha256 = downloader::sha256_file(&path).context(format!("Failed to read {:?}", path))?;
+    let catalog = manifest::fetch_catalog(config)?;
+    let Some(found) = find_match(config, &catalog, &file_name, &sha256, force) else {
+        let hint = if force {
+            ""
+        } else {
+            " (use --force to adopt it by name as an unknown version)"
+        };
+        fail!(
+            Kind::NotFound,
+            "{:?} (sha256 {}) does not match any tool in the configured repositories{}",
+            path,
+            sha256,
+            hint
+        );
+    };
+
+    adopt(config, &mut db, &path, &sha256, &found, force)?;
+    db.save(config)?;
+    report(&found);
+
+    Ok(())
+}
+
+/// Adopt every untracked file that matches a repository tool, e.g. after upgrading
+/// from a gpm that kept no installed-state database
+fn run_all(config: &Config, db: &mut InstalledDb, force: bool) -> Result<()> {
+    let untracked = db.untracked(config)?;
+    if untracked.is_empty() {
+        say!("No untracked files in {:?}", config.install_dir);
+        return Ok(());
+    }
+
+    let catalog = manifest::fetch_catalog(config)?;
+    let mut adopted = 0;
+    let mut unmatched = Vec::new();
+    let mut renames = Vec::new();
+    let mut failed = 0;
+
+    for file_name in &untracked {
+        let path = config.install_dir.join(file_name);
+        // A match adopted earlier in this run may have claimed the file by renaming it
+        if db.owns_file(&path) || fs::symlink_metadata(&path).is_err() {
+            continue;
+        }
+
+        let result = downloader::sha256_file(&path)
+            .context(format!("Failed to read {:?}", path))
+            .and_then(
+                |sha256| match find_match(config, &catalog, file_name, &sha256, force) {
+                    // Moving files around takes --force, so these are only listed
+                    Some(found) if found.name != file_name && !force => {
+                        renames.push(format!("{} ({})", file_name, found.name));
+                        Ok(None)
+                    }
+                    Some(found) => {
+                        adopt(config, db, &path, &sha256, &found, force).map(|()| Some(found))
+                    }
+                    None => {
+                        unmatched.push(file_name.as_str());
+                        Ok(None)
+                    }
+                },
+            );
+
+        match result {
+            Ok(Some(found)) => {
+                // Saved as it goes, so an interrupted run keeps what it adopted
+                db.save(config)?;
+                report(&found);
+                adopted += 1;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Failed to adopt {}: {:#}", file_name, e);
+                failed += 1;
+            }
+        }
+    }
+
+    say!("\nAdopted {} file(s)", adopted);
+    if !unmatched.is_empty() {
+        say!(
+            "Left untracked (no matching tool{}): {}",
+            if force { "" } else { "; see --force" },
+            unmatched.join(", ")
+        );
+    }
+    if !renames.is_empty() {
+        say!(
+            "Left untracked (match a tool of another name; --force renames them): {}",
+            renames.join(", ")
+        );
+    }
+    if failed > 0 {
+        fail!(Kind::Partial, "{} file(s) could not be adopted", failed);
+    }
+
+    Ok(())
+}
+
+/// The build `sha256` is, or with `force` the tool the file is named after
+///
+/// A tool of the same name wins; otherwise the first repository in resolution order.
+/// Older releases count too, so a binary that was never upgraded is recognised.
+fn find_match<'a>(
+    config: &Config,
+    catalog: &'a Catalog,
+    file_name: &str,
+    sha256: &str,
+    force: bool,
+) -> Option<Match<'a>> {
+    let mut matches = Vec::new();
+    for r in &catalog.repos {
+        for (name, tool) in &r.manifest.tools {
+            for release in tool.releases() {
+                let Some(artifact) = release.artifact_for(&config.targets) else {
+                    continue;
+                };
+                if artifact.archive.is_none() && artifact.sha256 == sha256 {
+                    matches.push(Match {
+                        name,
+                        release,
+                        artifact,
+                        repo: r,
+                        exact: true,
+                    });
+                }
+            }
+        }
+    }
+    matches.sort_by_key(|m| m.name != file_name);
+    if let Some(found) = matches.into_iter().next() {
+        return Some(found);
+    }
+    if !force {
+        return None;
+    }
+
+    catalog.repos.iter().find_map(|repo| {
+        let (name, tool) = repo.manifest.tools.get_key_value(file_name)?;
+        let artifact = tool
+            .artifact_for(&config.targets)
+            .filter(|artifact| artifact.archive.is_none())?;
+        Some(Match {
+            name,
+            release: Cow::Borrowed(tool),
+            artifact,
+            repo,
+            exact: false,
+        })
+    })
+}
+
+/// Record `path` as the matched tool, renaming it to the tool's name with `force`
+fn adopt(
+    config: &Config,
+    db: &mut InstalledDb,
+    path: &Path,
+    sha256: &str,
+    found: &Match,
+    force: bool,
+) -> Result<()> {
+    let name = found.name;
+    let version = if found.exact {
+        found.release.version.as_str()
+    } else {
+        UNKNOWN_VERSION
+    };
+    if db.get(name).is_some() {
+        fail!(
+            Kind::Usage,
+            "{:?} matches {} v{}, which is already installed",
+            path,
+            name,
+            version
+        );
+    }
+
+    // Tracked tools live under their own name
+    let dest = config.install_dir.join(name);
+    if dest != path {
+        if fs::symlink_metadata(&dest).is_ok() {
+            fail!(
+                Kind::Usage,
+                "{:?} matches {}, but {:?} is already taken",
+                path,
+                name,
+                dest
+            );
+        }
+        if !force {
+            fail!(
+                Kind::Usage,
+                "{:?} matches {} v{}; use --force to rename it to {} and adopt it",
+                path,
+                name,
+                version,
+                name
+            );
+        }
+        fs::rename(path, &dest).context(format!("Failed to rename {:?} to {:?}", path, dest))?;
+        say!("Renamed {} to {}", file_name(path), name);
+    }
+
+    db.record(
+        name,
+        InstalledTool {
+            version: version.to_string(),
+            sha256: sha256.to_string(),
+            url: found.artifact.url.clone(),
+            installed_at: state::now_unix(),
+            repo: found.repo.repo.name.clone(),
+            repo_version: found.repo.manifest.repo_version.clone(),
+            size: fs::metadata(&dest).ok().map(|m| m.len()),
+            archive: None,
+            files: Vec::new(),
+            channel: None,
+            depends: found
+                .release
+                .depends
+                .iter()
+                .map(|dependency| install::tool_name(dependency).to_string())
+                .collect(),
+        },
+    );
+
+    Ok(())
+}
+
+fn report(found: &Match) {
+    if found.exact {
+        say!(
+            "Adopted {} v{} from repository {}",
+            found.name,
+            found.release.version,
+            found.repo.repo.name
+        );
+    } else {
+        say!(
+            "Adopted {} as an unknown version from repository {}; 'gpm upgrade' will replace it",
+            found.name,
+            found.repo.repo.name
+        );
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// A file name in the install dir, or a path that must point into it
+fn locate(config: &Config, file: &str) -> Result<PathBuf> {
+    let path = if file.contains('/') {
+        std::path::absolute(file).context(format!("Invalid path: {}", file))?
+    } else {
+        config.install_dir.join(file)
+    };
+
+    let install_dir =
+        std::path::absolute(&config.install_dir).unwrap_or_else(|_| config.install_dir.clone());
+    if path.parent() != Some(install_dir.as_path()) {
+        fail!(
+            Kind::Usage,
+            "Only files directly in {:?} can be adopted; move {:?} there first",
+            config.install_dir,
+            path
+        );
+    }
+    if !is_regular_file(&path) {
+        fail!(Kind::NotFound, "{:?} is not a file", path);
+    }
+
+    Ok(config
+        .install_dir
+        .join(path.file_name().unwrap_or_default()))
+}
+
+fn is_regular_file(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|m| m.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::DEFAULT_TARGET;
+    use crate::version::Version;
+    use serde_json::json;
+
+    fn catalog(make_sha: &str) -> Catalog {
+        Catalog::for_tests(&[(
+            "main",
+            json!({
+                "make": {
+                    "version": "4.4.1",
+                    "targets": {DEFAULT_TARGET: {"url": "https://example.invalid/make", "sha256": make_sha}},
+                    "history": [{
+                        "version": "4.3",
+                        "targets": {DEFAULT_TARGET: {"url": "https://example.invalid/make-4.3", "sha256": "old"}},
+                    }],
+                    "depends": ["libc@>=1"],
+                },
+            }),
+        )])
+    }
+
+    #[test]
+    fn adopts_an_older_build_by_checksum_and_renames_it_with_force() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(dir.path());
+        let catalog = catalog("new");
+        let mut db = InstalledDb::default();
+        let path = config.install_dir.join("gmake");
+        fs::write(&path, "old make").unwrap();
+
+        let found = find_match(&config, &catalog, "gmake", "old", false).unwrap();
+        assert!(found.exact);
+        adopt(&config, &mut db, &path, "old", &found, true).unwrap();
+
+        assert!(!path.exists());
+        let record = db.get("make").unwrap();
+        assert_eq!(record.version, "4.3");
+        assert_eq!(record.url, "https://example.invalid/make-4.3");
+        assert_eq!(record.size, Some(8));
+        assert_eq!(record.depends, ["libc"]);
+    }
+
+    #[test]
+    fn adopts_an_unknown_build_by_name_only_with_force() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(dir.path());
+        let catalog = catalog("new");
+        let mut db = InstalledDb::default();
+        let path = config.install_dir.join("make");
+        fs::write(&path, "locally built make").unwrap();
+
+        assert!(find_match(&config, &catalog, "make", "local", false).is_none());
+        assert!(find_match(&config, &catalog, "gmake", "local", true).is_none());
+
+        let found = find_match(&config, &catalog, "make", "local", true).unwrap();
+        assert!(!found.exact);
+        adopt(&config, &mut db, &path, "local", &found, true).unwrap();
+
+        let record = db.get("make").unwrap();
+        assert_eq!(record.version, UNKNOWN_VERSION);
+        assert_eq!(record.sha256, "local");
+        // So that upgrade replaces it
+        assert!(Version::parse(UNKNOWN_VERSION) < Version::parse("4.4.1"));
+    }
+
+    #[test]
+    fn refuses_a_match_whose_name_is_taken() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(dir.path());
+        let catalog = catalog("new");
+        let mut db = InstalledDb::default();
+        fs::write(config.install_dir.join("make"), "something else").unwrap();
+        let path = config.install_dir.join("make-copy");
+        fs::write(&path, "new make").unwrap();
+
+        let found = find_match(&config, &catalog, "make-copy", "new", false).unwrap();
+        assert!(adopt(&config, &mut db, &path, "new", &found, true).is_err());
+        assert!(path.exists());
+        assert!(db.get("make").is_none());
+    }
+
+    #[test]
+    fn leaves_a_file_under_another_name_alone_without_force() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(dir.path());
+        let catalog = catalog("new");
+        let mut db = InstalledDb::default();
+        let path = config.install_dir.join("gmake");
+        fs::write(&path, "new make").unwrap();
+
+        let found = find_match(&config, &catalog, "gmake", "new", false).unwrap();
+        let error = adopt(&config, &mut db, &path, "new", &found, false).unwrap_err();
+
+        assert!(error.to_string().contains("--force"), "{}", error);
+        assert!(path.exists());
+        assert!(!config.install_dir.join("make").exists());
+        assert!(db.get("make").is_none());
+    }
+
+    #[test]
+    fn adopts_a_file_already_named_after_its_tool_without_force() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(dir.path());
+        let catalog = catalog("new");
+        let mut db = InstalledDb::default();
+        let path = config.install_dir.join("make");
+        fs::write(&path, "new make").unwrap();
+
+        let found = find_match(&config, &catalog, "make", "new", false).unwrap();
+        adopt(&config, &mut db, &path, "new", &found, false).unwrap();
+
+        assert!(path.exists());
+        assert_eq!(db.get("make").unwrap().version, "4.4.1");
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::PathBuf;
+
+use crate::cli::ConfigAction;
+use crate::config::{self, Settings, SYSTEM_CONFIG_PATH};
+use crate::output::{self, say, OutputFormat};
+
+/// One setting, as printed by `gpm config get|list --output json`
+#[derive(Serialize)]
+struct Entry {
+    key: String,
+    value: Option<String>,
+    /// Layer the value comes from (only for `config list`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+struct Document {
+    system_file: PathBuf,
+    user_file: Option<PathBuf>,
+    settings: Vec<Entry>,
+}
+
+pub fn run(action: &ConfigAction, cli_settings: Settings) -> Result<()> {
+    match action {
+        ConfigAction::Get { key } => get(key, cli_settings),
+        ConfigAction::Set { key, value, system } => {
+            let path = target_path(*system)?;
+            let mut settings = Settings::load(&path)?;
+            settings.set(key, value)?;
+            settings.save(&path)?;
+            say!("Set {} = {} in {:?}", key, value, path);
+            Ok(())
+        }
+        ConfigAction::Unset { key, system } => {
+            let path = target_path(*system)?;
+            let mut settings = Settings::load(&path)?;
+            settings.unset(key)?;
+            settings.save(&path)?;
+            say!("Removed {} from {:?}", key, path);
+            Ok(())
+        }
+        ConfigAction::List => list(cli_settings),
+    }
+}
+
+fn get(key: &str, cli_settings: Settings) -> Result<()> {
+    let settings = Settings::layered(cli_settings)?.with_defaults();
+
+    let value = settings.get(key)?;
+
+    match output::format() {
+        OutputFormat::Text => println!("{}", value.unwrap_or_default()),
+        OutputFormat::Json => output::emit_json(&Entry {
+            key: key.to_string(),
+            value,
+            source: None,
+        }),
+        OutputFormat::Tsv => output::emit_tsv(
+            &["key", "value"],
+            &[vec![key.to_string(), value.unwrap_or_default()]],
+        ),
+    }
+
+    Ok(())
+}
+
+fn list(cli_settings: Settings) -> Result<()> {
+    let system_path = PathBuf::from(SYSTEM_CONFIG_PATH);
+    let user_path = config::user_config_path();
+
+    // Highest precedence first, so the first layer with a value names the source
+    let mut layers = vec![
+        ("command line", cli_settings),
+        ("environment", Settings::from_env()?),
+    ];
+    if let Some(path) = &user_path {
+        layers.push(("user file", Settings::load(path)?));
+    }
+    layers.push(("system file", Settings::load(&system_path)?));
+    layers.push(("default", Settings::default().with_defaults()));
+
+    let mut entries = Vec::new();
+    for key in Settings::KEYS {
+        let mut found = None;
+        for (source, layer) in &layers {
+            if let Some(value) = layer.get(key)? {
+                found = Some((value, *source));
+                break;
+            }
+        }
+
+        entries.push(Entry {
+            key: key.to_string(),
+            value: found.as_ref().map(|(value, _)| value.clone()),
+            source: Some(found.map_or("default", |(_, source)| source)),
+        });
+    }
+
+    match output::format() {
+        OutputFormat::Text => {
+            println!("System file: {:?}", system_path);
+            match &user_path {
+                Some(path) => println!("User file:   {:?}\n", path),
+                None => println!("User file:   (none, HOME is not set)\n"),
+            }
+
+            println!("{:<20}\t{:<40}\tSOURCE", "KEY", "VALUE");
+            println!("{}", "-".repeat(80));
+
+            for entry in &entries {
+                let value = entry.value.as_deref().unwrap_or("(unset)");
+                println!(
+                    "{:<20}\t{:<40}\t{}",
+                    entry.key,
+                    value,
+                    entry.source.unwrap_or_default()
+                );
+            }
+        }
+        OutputFormat::Json => output::emit_json(&Document {
+            system_file: system_path,
+            user_file: user_path,
+            settings: entries,
+        }),
+        OutputFormat::Tsv => output::emit_tsv(
+            &["key", "value", "source"],
+            &entries
+                .into_iter()
+                .map(|e| {
+                    vec![
+                        e.key,
+                        e.value.unwrap_or_default(),
+                        e.source.unwrap_or_default().to_string(),
+                    ]
+                })
+                .collect::<Vec<_>>(),
+        ),
+    }
+
+    Ok(())
+}
+
+/// The gpm.toml that `--system` selects, or the user one
+pub(super) fn target_path(system: bool) -> Result<PathBuf> {
+    if system {
+        return Ok(PathBuf::from(SYSTEM_CONFIG_PATH));
+    }
+
+    config::user_config_path()
+        .context("Cannot locate the user configuration file (set GPM_CONFIG or HOME)")
+}
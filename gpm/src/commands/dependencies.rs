@@ -0,0 +1,408 @@
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+
+use crate::commands::install::{split_version, tool_name};
+use crate::config::Config;
+use crate::manifest::{Catalog, Tool};
+use crate::output::{fail, say, Kind};
+use crate::state::InstalledDb;
+use crate::version::Constraint;
+
+/// What an install run has to do, dependencies first
+#[derive(Default)]
+pub(super) struct Plan {
+    /// Specs in installation order: every tool comes after the tools it depends on
+    pub order: Vec<String>,
+    /// Specs in `order` that were pulled in by another tool rather than asked for
+    pub added: Vec<String>,
+    /// Requested specs that cannot be installed, with the reason
+    pub failed: Vec<(String, anyhow::Error)>,
+    /// Tools in `order` each planned tool depends on, by tool name
+    pub needs: HashMap<String, Vec<String>>,
+}
+
+/// Which tools a plan may add besides the ones asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Scope {
+    /// Required and recommended tools
+    Recommends,
+    /// Required tools only
+    Depends,
+    /// Nothing new: every dependency must already be installed, as for an upgrade
+    Installed,
+}
+
+/// Work out which tools installing `specs` takes, and in which order
+///
+/// Dependencies already installed at a matching version are left alone. A
+/// requested tool with a missing dependency or a dependency cycle fails on its
+/// own; a recommendation that cannot be installed is only reported.
+pub(super) fn plan(
+    config: &Config,
+    catalog: &Catalog,
+    db: &InstalledDb,
+    specs: &[String],
+    scope: Scope,
+) -> Plan {
+    plan_for(config, catalog, db, specs, &config.targets, scope)
+}
+
+/// `plan` for builds of `targets` instead of this host's, as a mirror exports them;
+/// no targets means any build will do
+pub(super) fn plan_for(
+    config: &Config,
+    catalog: &Catalog,
+    db: &InstalledDb,
+    specs: &[String],
+    targets: &[String],
+    scope: Scope,
+) -> Plan {
+    let mut planner = Planner {
+        config,
+        catalog,
+        db,
+        targets,
+        scope,
+        stack: Vec::new(),
+        planned: HashSet::new(),
+        plan: Plan::default(),
+    };
+
+    for spec in specs {
+        if let Err(e) = planner.visit(spec) {
+            planner.plan.failed.push((spec.clone(), e));
+        }
+    }
+
+    let mut plan = planner.plan;
+    let requested: HashSet<&str> = specs.iter().map(|spec| tool_name(spec)).collect();
+    plan.added = plan
+        .order
+        .iter()
+        .filter(|spec| !requested.contains(tool_name(spec)))
+        .cloned()
+        .collect();
+    plan
+}
+
+struct Planner<'a> {
+    config: &'a Config,
+    catalog: &'a Catalog,
+    db: &'a InstalledDb,
+    /// Targets a dependency needs a build for
+    targets: &'a [String],
+    scope: Scope,
+    /// Tools whose dependencies are being planned, outermost first
+    stack: Vec<String>,
+    /// Names of the tools in `plan.order`
+    planned: HashSet<String>,
+    plan: Plan,
+}
+
+impl Planner<'_> {
+    /// Plan `spec` after everything it depends on
+    fn visit(&mut self, spec: &str) -> Result<()> {
+        let name = tool_name(spec);
+        if self.planned.contains(name) {
+            return Ok(());
+        }
+        if let Some(start) = self.stack.iter().position(|n| n == name) {
+            let mut cycle = self.stack[start..].to_vec();
+            cycle.push(name.to_string());
+            fail!(Kind::Usage, "Dependency cycle: {}", cycle.join(" -> "));
+        }
+
+        let (base, _) = split_version(spec);
+        let resolved = match self.catalog.resolve(self.config, base, self.db.get(name)) {
+            Ok(resolved) => resolved,
+            // A requested tool that does not resolve is reported by its install
+            Err(_) if self.stack.is_empty() => {
+                self.push(spec, Vec::new());
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        if !self.stack.is_empty() && !self.has_build(resolved.tool) {
+            fail!(
+                Kind::NotFound,
+                "Tool '{}' has no build for {}",
+                name,
+                if self.targets.is_empty() {
+                    "any target".to_string()
+                } else {
+                    self.targets.join(", ")
+                }
+            );
+        }
+
+        // Whatever this tool pulled in is dropped again if it cannot be installed
+        let mark = self.plan.order.len();
+        self.stack.push(name.to_string());
+        let result =
+            self.visit_dependencies(name, &resolved.tool.depends, &resolved.tool.recommends);
+        self.stack.pop();
+
+        match result {
+            Ok(needs) => {
+                self.push(spec, needs);
+                Ok(())
+            }
+            Err(e) => {
+                for dropped in self.plan.order.drain(mark..) {
+                    self.planned.remove(tool_name(&dropped));
+                    self.plan.needs.remove(tool_name(&dropped));
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Plan the dependencies of `name` and return the ones it cannot do without
+    fn visit_dependencies(
+        &mut self,
+        name: &str,
+        depends: &[String],
+        recommends: &[String],
+    ) -> Result<Vec<String>> {
+        let mut needs = Vec::new();
+
+        for dependency in depends {
+            if self.is_satisfied(dependency)? {
+                continue;
+            }
+            if self.scope == Scope::Installed && self.db.get(tool_name(dependency)).is_none() {
+                fail!(
+                    Kind::NotFound,
+                    "'{}' is not installed, and upgrade installs nothing new (run 'gpm install {}')",
+                    tool_name(dependency),
+                    self.stack[0]
+                );
+            }
+            self.visit(dependency)
+                .context(format!("'{}' depends on '{}'", name, dependency))?;
+            needs.push(tool_name(dependency).to_string());
+        }
+
+        if self.scope == Scope::Recommends {
+            for recommended in recommends {
+                if self.is_satisfied(recommended).unwrap_or(true) {
+                    continue;
+                }
+                if let Err(e) = self.visit(recommended) {
+                    say!("Skipping {}, recommended by {}: {:#}", recommended, name, e);
+                }
+            }
+        }
+
+        Ok(needs)
+    }
+
+    /// Whether an installed tool already fulfils a `name` or `name@constraint` dependency
+    fn is_satisfied(&self, spec: &str) -> Result<bool> {
+        let Some(installed) = self.db.get(tool_name(spec)) else {
+            return Ok(false);
+        };
+
+        match split_version(spec) {
+            (_, Some(constraint)) => Ok(Constraint::parse(constraint)?.matches(&installed.version)),
+            (_, None) => Ok(true),
+        }
+    }
+
+    fn has_build(&self, tool: &Tool) -> bool {
+        if self.targets.is_empty() {
+            tool.url.is_some() || !tool.targets.is_empty()
+        } else {
+            tool.artifact_for(self.targets).is_some()
+        }
+    }
+
+    fn push(&mut self, spec: &str, needs: Vec<String>) {
+        let name = tool_name(spec).to_string();
+        self.planned.insert(name.clone());
+        self.plan.needs.insert(name, needs);
+        self.plan.order.push(spec.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::DEFAULT_TARGET;
+    use serde_json::{json, Value};
+
+    /// A tool with a build for this host and the given relations
+    fn tool(depends: &[&str], recommends: &[&str]) -> Value {
+        json!({
+            "version": "1.0",
+            "targets": {DEFAULT_TARGET: {"url": "https://example.invalid/tool", "sha256": "0"}},
+            "depends": depends,
+            "recommends": recommends,
+        })
+    }
+
+    fn run(tools: Value, specs: &[&str]) -> Plan {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(dir.path());
+        let catalog = Catalog::for_tests(&[("main", tools)]);
+        let specs: Vec<String> = specs.iter().map(|s| s.to_string()).collect();
+        plan(
+            &config,
+            &catalog,
+            &InstalledDb::default(),
+            &specs,
+            Scope::Recommends,
+        )
+    }
+
+    fn failures(plan: &Plan) -> Vec<String> {
+        plan.failed
+            .iter()
+            .map(|(spec, e)| format!("{}: {:#}", spec, e))
+            .collect()
+    }
+
+    #[test]
+    fn plans_a_chain_deepest_first() {
+        let plan = run(
+            json!({"a": tool(&["b"], &[]), "b": tool(&["c@>=1"], &[]), "c": tool(&[], &[])}),
+            &["a"],
+        );
+
+        assert_eq!(plan.order, ["c@>=1", "b", "a"]);
+        assert_eq!(plan.added, ["c@>=1", "b"]);
+        assert_eq!(plan.needs["a"], ["b"]);
+        assert_eq!(plan.needs["b"], ["c"]);
+        assert!(plan.failed.is_empty());
+    }
+
+    #[test]
+    fn plans_a_shared_dependency_once() {
+        let plan = run(
+            json!({
+                "top": tool(&["left", "right"], &[]),
+                "left": tool(&["base"], &[]),
+                "right": tool(&["base"], &[]),
+                "base": tool(&[], &[]),
+            }),
+            &["top"],
+        );
+
+        assert_eq!(plan.order, ["base", "left", "right", "top"]);
+        assert!(plan.failed.is_empty());
+    }
+
+    #[test]
+    fn fails_a_tool_in_a_cycle_and_keeps_the_others() {
+        let plan = run(
+            json!({
+                "a": tool(&["b"], &[]),
+                "b": tool(&["a"], &[]),
+                "lone": tool(&[], &[]),
+            }),
+            &["a", "lone"],
+        );
+
+        assert_eq!(plan.order, ["lone"]);
+        let failures = failures(&plan);
+        assert_eq!(failures.len(), 1);
+        assert!(
+            failures[0].contains("Dependency cycle: a -> b -> a"),
+            "{}",
+            failures[0]
+        );
+    }
+
+    #[test]
+    fn fails_a_tool_with_a_missing_dependency_but_skips_a_missing_recommendation() {
+        let plan = run(
+            json!({
+                "a": tool(&["b"], &[]),
+                "b": tool(&["gone"], &[]),
+                "c": tool(&[], &["gone", "b"]),
+            }),
+            &["a", "c"],
+        );
+
+        // Nothing `a` pulled in stays planned, and `c` does without its recommendations
+        assert_eq!(plan.order, ["c"]);
+        let failures = failures(&plan);
+        assert_eq!(failures.len(), 1);
+        assert!(
+            failures[0].starts_with("a: 'a' depends on 'b'"),
+            "{}",
+            failures[0]
+        );
+        assert!(failures[0].contains("'gone' not found"), "{}", failures[0]);
+    }
+
+    #[test]
+    fn plans_for_other_targets() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(dir.path());
+        let arm = "aarch64-unknown-linux-musl".to_string();
+        let catalog = Catalog::for_tests(&[(
+            "main",
+            json!({
+                "a": tool(&["armonly"], &[]),
+                "armonly": {
+                    "version": "1.0",
+                    "targets": {&arm: {"url": "https://example.invalid/arm", "sha256": "1"}},
+                },
+            }),
+        )]);
+        let specs = vec!["a".to_string()];
+        let db = InstalledDb::default();
+
+        assert_eq!(
+            plan(&config, &catalog, &db, &specs, Scope::Recommends)
+                .order
+                .len(),
+            0
+        );
+        let plan = plan_for(&config, &catalog, &db, &specs, &[arm], Scope::Recommends);
+        assert_eq!(plan.order, ["armonly", "a"]);
+        let plan = plan_for(&config, &catalog, &db, &specs, &[], Scope::Recommends);
+        assert_eq!(plan.order, ["armonly", "a"]);
+    }
+
+    #[test]
+    fn upgrades_add_no_new_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(dir.path());
+        let catalog = Catalog::for_tests(&[(
+            "main",
+            json!({
+                "a": tool(&["b@>=2", "new"], &["extra"]),
+                "b": tool(&[], &[]),
+                "new": tool(&[], &[]),
+                "extra": tool(&[], &[]),
+                "c": tool(&["b@>=2"], &[]),
+            }),
+        )]);
+        let mut db = InstalledDb::default();
+        for (name, version) in [("a", "0.9"), ("b", "1.0"), ("c", "0.9")] {
+            let record: crate::state::InstalledTool = serde_json::from_value(json!({
+                "version": version, "sha256": "0", "url": "", "installed_at": 0,
+                "repo": "main", "repo_version": "1.0.0",
+            }))
+            .unwrap();
+            db.record(name, record);
+        }
+        let specs = vec!["a".to_string(), "c".to_string()];
+
+        let plan = plan(&config, &catalog, &db, &specs, Scope::Installed);
+
+        // An installed dependency is upgraded along; a new one is only named
+        assert_eq!(plan.order, ["b@>=2", "c"]);
+        assert_eq!(plan.added, ["b@>=2"]);
+        let failures = failures(&plan);
+        assert_eq!(failures.len(), 1);
+        assert!(
+            failures[0].contains("'new' is not installed"),
+            "{}",
+            failures[0]
+        );
+        assert!(failures[0].contains("gpm install a"), "{}", failures[0]);
+    }
+}
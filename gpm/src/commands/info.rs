@@ -0,0 +1,433 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::fmt::Display;
+use std::path::PathBuf;
+
+use crate::commands::install;
+use crate::config::Config;
+use crate::downloader;
+use crate::manifest::{self, Artifact, Resolved, Tool, STABLE_CHANNEL};
+use crate::output::{self, say, OutputFormat};
+use crate::platform::DEFAULT_TARGET;
+use crate::state::{InstalledDb, InstalledTool};
+use crate::timestamp;
+use crate::transport;
+
+pub fn run(config: &Config, spec: &str) -> Result<()> {
+    let db = InstalledDb::load(config)?;
+    let tool_name = install::tool_name(spec);
+    let installed = db.get(tool_name);
+
+    // An installed tool can still be described when no manifest is reachable
+    let catalog = match manifest::fetch_catalog(config) {
+        Ok(catalog) => Some(catalog),
+        Err(e) if installed.is_some() => {
+            eprintln!("Warning: {:#}", e);
+            None
+        }
+        Err(e) => return Err(e),
+    };
+    let resolved = match catalog
+        .as_ref()
+        .map(|catalog| catalog.resolve(config, install::split_version(spec).0, installed))
+    {
+        Some(Ok(resolved)) => Some(resolved),
+        Some(Err(e)) if installed.is_none() => return Err(e),
+        Some(Err(e)) => {
+            if config.verbose {
+                say!("{}", e);
+            }
+            None
+        }
+        None => None,
+    };
+    let also_in: Vec<String> = match &resolved {
+        Some(resolved) => catalog
+            .iter()
+            .flat_map(|catalog| catalog.providers(tool_name))
+            .map(|other| other.repo.name.clone())
+            .filter(|name| *name != resolved.repo.name)
+            .collect(),
+        None => Vec::new(),
+    };
+
+    if !output::is_text() {
+        let document = document(
+            config,
+            &db,
+            tool_name,
+            installed,
+            resolved.as_ref(),
+            also_in,
+        );
+        match output::format() {
+            OutputFormat::Json => output::emit_json(&document),
+            _ => output::emit_tsv(&["field", "value"], &document.rows()),
+        }
+        return Ok(());
+    }
+
+    println!("{}", tool_name);
+
+    match &resolved {
+        Some(resolved) => {
+            print_remote(config, resolved);
+            if !also_in.is_empty() {
+                field("Also in", also_in.join(", "));
+            }
+        }
+        None => println!("  Not available from any configured repository"),
+    }
+
+    println!("\nInstalled:");
+    match installed {
+        Some(current) => print_installed(config, &db, tool_name, current, resolved.as_ref()),
+        None => println!("  No"),
+    }
+    if let Some(pinned) = db.pins.get(tool_name) {
+        field("Pinned", format!("{} (see 'gpm unpin')", pinned));
+    }
+
+    Ok(())
+}
+
+/// Everything `gpm info --output json` prints about a tool
+#[derive(Serialize)]
+struct Document<'a> {
+    name: &'a str,
+    /// The manifest entry a bare `gpm install` would use, if any repository lists the tool
+    remote: Option<Remote<'a>>,
+    installed: Option<Installed<'a>>,
+    /// Version set by `gpm pin`
+    pinned: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct Remote<'a> {
+    repo: &'a str,
+    repo_version: &'a str,
+    /// Other repositories listing the same name
+    also_in: Vec<String>,
+    /// Target triple of the build this host would install
+    host_target: Option<String>,
+    /// The manifest entry exactly as published
+    tool: &'a Tool,
+}
+
+#[derive(Serialize)]
+struct Installed<'a> {
+    #[serde(flatten)]
+    record: &'a InstalledTool,
+    path: PathBuf,
+    /// sha256 of the installed binary now, `None` for packages or unreadable files
+    sha256_on_disk: Option<String>,
+    /// Whether the manifest build for this host is the installed one
+    up_to_date: Option<bool>,
+    /// Release channel the tool follows
+    follows: String,
+    /// Installed tools that depend on this one
+    needed_by: Vec<&'a str>,
+    /// Versions `gpm rollback` can restore, newest first
+    rollback_versions: Vec<String>,
+}
+
+fn document<'a>(
+    config: &Config,
+    db: &'a InstalledDb,
+    tool_name: &'a str,
+    installed: Option<&'a InstalledTool>,
+    resolved: Option<&Resolved<'a>>,
+    also_in: Vec<String>,
+) -> Document<'a> {
+    let remote = resolved.map(|resolved| Remote {
+        repo: resolved.repo.name.as_str(),
+        repo_version: resolved.repo_version,
+        also_in,
+        host_target: host_target(config, resolved.tool).cloned(),
+        tool: resolved.tool,
+    });
+
+    let installed = installed.map(|record| {
+        let path = match record.archive {
+            Some(_) => config.package_dir(tool_name),
+            None => config.install_dir.join(tool_name),
+        };
+        let sha256_on_disk = match record.archive {
+            Some(_) => None,
+            None => downloader::sha256_file(&path).ok(),
+        };
+        Installed {
+            record,
+            sha256_on_disk,
+            path,
+            up_to_date: resolved
+                .map(|resolved| resolved.tool.on_channel(&follows(record, resolved)))
+                .filter(|tool| tool.artifact_for(&config.targets).is_some())
+                .map(|tool| install::is_up_to_date(config, record, &tool)),
+            follows: resolved
+                .map(|resolved| follows(record, resolved))
+                .or(record.channel.clone())
+                .unwrap_or_else(|| STABLE_CHANNEL.to_string()),
+            needed_by: db.dependents(tool_name),
+            rollback_versions: db
+                .history
+                .get(tool_name)
+                .map(|history| history.iter().rev().map(|t| t.version.clone()).collect())
+                .unwrap_or_default(),
+        }
+    });
+
+    Document {
+        name: tool_name,
+        remote,
+        installed,
+        pinned: db.pins.get(tool_name).map(String::as_str),
+    }
+}
+
+impl Document<'_> {
+    /// The main fields as `field`/`value` pairs for TSV
+    fn rows(&self) -> Vec<Vec<String>> {
+        let mut rows = vec![("name", self.name.to_string())];
+
+        if let Some(remote) = &self.remote {
+            let tool = remote.tool;
+            rows.extend([
+                ("repo", remote.repo.to_string()),
+                ("repo_version", remote.repo_version.to_string()),
+                ("version", tool.version.clone()),
+                ("description", tool.description.clone()),
+                ("license", tool.license.clone()),
+                ("build_type", tool.build_type.clone()),
+                ("source_url", tool.source_url.clone()),
+                (
+                    "source_sha256",
+                    tool.source_sha256.clone().unwrap_or_default(),
+                ),
+                (
+                    "host_target",
+                    remote.host_target.clone().unwrap_or_default(),
+                ),
+                ("depends", tool.depends.join(" ")),
+                ("recommends", tool.recommends.join(" ")),
+            ]);
+        }
+
+        if let Some(installed) = &self.installed {
+            let record = installed.record;
+            rows.extend([
+                ("installed_version", record.version.clone()),
+                ("installed_repo", record.repo.clone()),
+                ("installed_at", record.installed_at.to_string()),
+                ("installed_url", record.url.clone()),
+                ("installed_sha256", record.sha256.clone()),
+                ("path", installed.path.display().to_string()),
+                (
+                    "sha256_on_disk",
+                    installed.sha256_on_disk.clone().unwrap_or_default(),
+                ),
+                (
+                    "up_to_date",
+                    installed
+                        .up_to_date
+                        .map(|v| v.to_string())
+                        .unwrap_or_default(),
+                ),
+                ("channel", installed.follows.clone()),
+            ]);
+        }
+
+        if let Some(pinned) = self.pinned {
+            rows.push(("pinned", pinned.to_string()));
+        }
+
+        rows.into_iter()
+            .map(|(field, value)| vec![field.to_string(), value])
+            .collect()
+    }
+}
+
+/// Channel an installed tool follows in the repository it resolves to
+fn follows(record: &InstalledTool, resolved: &Resolved) -> String {
+    install::channel(None, Some(record), resolved.repo).to_string()
+}
+
+/// Target whose build `Tool::artifact_for` picks for this host
+fn host_target<'a>(config: &'a Config, tool: &Tool) -> Option<&'a String> {
+    let has_legacy = tool.url.is_some() && tool.sha256.is_some();
+    config
+        .targets
+        .iter()
+        .find(|t| tool.targets.contains_key(*t) || (*t == DEFAULT_TARGET && has_legacy))
+}
+
+fn field(label: &str, value: impl Display) {
+    println!("  {:<15}{}", format!("{}:", label), value);
+}
+
+/// Every field of the manifest entry, one build per target
+fn print_remote(config: &Config, resolved: &Resolved) {
+    let tool = resolved.tool;
+
+    field("Description", &tool.description);
+    field("Version", &tool.version);
+    field(
+        "Repository",
+        format!(
+            "{} (manifest v{})",
+            resolved.repo.name, resolved.repo_version
+        ),
+    );
+    field("License", &tool.license);
+    field("Build type", &tool.build_type);
+    field("Source", &tool.source_url);
+    field(
+        "Source sha256",
+        tool.source_sha256.as_deref().unwrap_or("(not published)"),
+    );
+    if !tool.history.is_empty() {
+        let older: Vec<String> = tool
+            .releases()
+            .iter()
+            .skip(1)
+            .map(|release| match release.artifact_for(&config.targets) {
+                Some(_) => release.version.clone(),
+                None => format!("{} (no build for this host)", release.version),
+            })
+            .collect();
+        field("Previous", older.join(", "));
+    }
+    if !tool.depends.is_empty() {
+        field("Depends on", tool.depends.join(", "));
+    }
+    if !tool.recommends.is_empty() {
+        field("Recommends", tool.recommends.join(", "));
+    }
+    if !tool.channels.is_empty() {
+        let channels: Vec<String> = tool
+            .channels
+            .iter()
+            .map(|(channel, release)| format!("{} v{}", channel, release.version))
+            .collect();
+        field("Channels", channels.join(", "));
+    }
+
+    let host_target = host_target(config, tool);
+
+    println!("\nBuilds:");
+    for (target, artifact) in &tool.targets {
+        print_artifact(resolved, target, artifact, host_target == Some(target));
+    }
+
+    // The legacy fields describe a default-target build of their own
+    if let (Some(url), Some(sha256)) = (&tool.url, &tool.sha256) {
+        let legacy = Artifact {
+            url: url.clone(),
+            sha256: sha256.clone(),
+            mirrors: tool.mirrors.clone(),
+            size: tool.size,
+            archive: None,
+        };
+        let label = format!("{} (legacy fields)", DEFAULT_TARGET);
+        let for_host = host_target.is_some_and(|t| t == DEFAULT_TARGET)
+            && !tool.targets.contains_key(DEFAULT_TARGET);
+        print_artifact(resolved, &label, &legacy, for_host);
+    }
+
+    if host_target.is_none() {
+        println!("  No build for this host ({})", config.targets.join(", "));
+    }
+}
+
+fn print_artifact(resolved: &Resolved, label: &str, artifact: &Artifact, for_host: bool) {
+    if for_host {
+        println!("  {} (this host)", label);
+    } else {
+        println!("  {}", label);
+    }
+
+    let url = transport::join(&resolved.repo.url, &artifact.url);
+    println!("    {:<13}{}", "URL:", url);
+    for mirror in &artifact.mirrors {
+        println!("    {:<13}{}", "Mirror:", mirror);
+    }
+    println!("    {:<13}{}", "SHA256:", artifact.sha256);
+    println!("    {:<13}{} bytes", "Size:", artifact.size);
+    if let Some(archive) = &artifact.archive {
+        println!("    {:<13}{}", "Archive:", archive.format);
+        if !archive.bin.is_empty() {
+            println!("    {:<13}{}", "Executables:", archive.bin.join(", "));
+        }
+        if !archive.data.is_empty() {
+            println!("    {:<13}{}", "Data:", archive.data.join(", "));
+        }
+    }
+}
+
+/// What is on disk, and how it compares with the record and the manifest
+fn print_installed(
+    config: &Config,
+    db: &InstalledDb,
+    tool_name: &str,
+    current: &InstalledTool,
+    resolved: Option<&Resolved>,
+) {
+    field("Version", &current.version);
+    field(
+        "Repository",
+        format!("{} (manifest v{})", current.repo, current.repo_version),
+    );
+    field("Installed at", timestamp::format_unix(current.installed_at));
+    field("From", &current.url);
+    field("SHA256", &current.sha256);
+
+    if current.archive.is_some() {
+        field("Path", config.package_dir(tool_name).display());
+        for file in &current.files {
+            field("Link", file.display());
+        }
+    } else {
+        let path = config.install_dir.join(tool_name);
+        field("Path", path.display());
+
+        let on_disk = match downloader::sha256_file(&path) {
+            Ok(sha256) if sha256 == current.sha256 => format!("{} (matches)", sha256),
+            Ok(sha256) => format!("{} (MODIFIED since install)", sha256),
+            Err(e) => format!("unreadable ({})", e),
+        };
+        field("On disk", on_disk);
+    }
+
+    let dependents = db.dependents(tool_name);
+    if !dependents.is_empty() {
+        field("Needed by", dependents.join(", "));
+    }
+
+    match resolved {
+        Some(resolved) => field("Channel", follows(current, resolved)),
+        None => field(
+            "Channel",
+            current.channel.as_deref().unwrap_or(STABLE_CHANNEL),
+        ),
+    }
+
+    if let Some(resolved) = resolved {
+        let tool = resolved.tool.on_channel(&follows(current, resolved));
+        let status = match tool.artifact_for(&config.targets) {
+            Some(_) if install::is_up_to_date(config, current, &tool) => "up to date".to_string(),
+            Some(artifact) => format!(
+                "v{} available, sha256 {} (run 'gpm upgrade {}')",
+                tool.version, artifact.sha256, tool_name
+            ),
+            None => "no build for this host in the manifest".to_string(),
+        };
+        field("Manifest", status);
+    }
+
+    if let Some(history) = db.history.get(tool_name) {
+        let kept: Vec<&str> = history.iter().rev().map(|t| t.version.as_str()).collect();
+        if !kept.is_empty() {
+            field("Rollback to", kept.join(", "));
+        }
+    }
+}
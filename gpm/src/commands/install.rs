@@ -1,115 +1,534 @@
 use anyhow::{Context, Result};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fs;
-use std::os::unix::fs::PermissionsExt;
 
-use crate::config::Config;
-use crate::downloader;
-use crate::manifest;
+use crate::archive;
+use crate::commands::dependencies::{self, Scope};
+use crate::config::{self, Config, Repo};
+use crate::downloader::{self, Download};
+use crate::manifest::{self, Catalog, Tool, STABLE_CHANNEL};
+use crate::mirrors;
+use crate::output::{self, fail, say, Kind, OutputFormat};
+use crate::state::{self, InstalledDb, InstalledTool};
+use crate::version::{self, Constraint};
+use crate::versions;
+
+pub fn run(
+    config: &Config,
+    tool_patterns: &[String],
+    all: bool,
+    force: bool,
+    channel: Option<&str>,
+    recommends: bool,
+) -> Result<()> {
+    if let Some(channel) = channel {
+        config::validate_channel_name(channel)?;
+    }
+    let catalog = manifest::fetch_catalog(config)?;
+
+    // Wildcards only select tools built for this host; exact names are rejected individually
+    let mut available = catalog.available(config);
+    available.extend(catalog.qualified(config));
+    let tools_to_install = expand_patterns(tool_patterns, all, &available, "install")?;
+
+    let mut db = InstalledDb::load(config)?;
+    let scope = if recommends {
+        Scope::Recommends
+    } else {
+        Scope::Depends
+    };
+    let summary = install_tools(
+        config,
+        &catalog,
+        &mut db,
+        &tools_to_install,
+        force,
+        channel,
+        scope,
+    );
+    summary.emit();
+
+    // Check if PATH is configured (only show once)
+    if summary.installed > 0 || summary.upgraded > 0 {
+        if let Ok(path_env) = std::env::var("PATH") {
+            let install_dir_str = config.install_dir.to_string_lossy();
+            if !path_env.contains(&install_dir_str.to_string()) {
+                say!("\nWarning: Install directory not in PATH");
+                say!("Run 'gpm setup-path' to configure your shell automatically");
+            }
+        }
+    }
 
-pub fn run(config: &Config, tool_patterns: &[String], all: bool) -> Result<()> {
-    let manifest = manifest::fetch_manifest(config)?;
+    if !summary.failed.is_empty() {
+        fail!(Kind::Partial, "Some tools failed to install");
+    }
+
+    Ok(())
+}
 
+/// Expand user-supplied names and wildcards against `candidates`
+///
+/// Exact names are passed through even if they are not candidates so the
+/// caller can report them individually. Patterns of the form `repo/name` only
+/// match qualified candidates, and bare patterns only bare ones.
+pub(super) fn expand_patterns(
+    tool_patterns: &[String],
+    all: bool,
+    candidates: &[String],
+    action: &str,
+) -> Result<Vec<String>> {
     // Sanitize arguments - if all flag is set or no patterns provided, treat as "*"
     let patterns = if all || tool_patterns.is_empty() {
         vec!["*".to_string()]
     } else {
-        // Filter out patterns that look like shell-expanded paths (contain / or .)
+        // Filter out patterns that look like shell-expanded paths; `repo/name` is allowed
         tool_patterns
             .iter()
-            .filter(|p| !p.contains('/') && !p.starts_with('.'))
+            .filter(|p| p.matches('/').count() <= 1 && !p.starts_with(['.', '/']))
             .cloned()
             .collect::<Vec<_>>()
     };
 
     if patterns.is_empty() {
-        anyhow::bail!("No valid tool patterns specified. Use --all to install all tools.");
+        fail!(
+            Kind::Usage,
+            "No valid tool patterns specified. Use --all to {} all tools.",
+            action
+        );
     }
 
     // Expand patterns to actual tool names
-    let mut tools_to_install = Vec::new();
+    let mut tool_names = Vec::new();
 
     for pattern in &patterns {
+        let qualified = pattern.contains('/');
+        let candidates = candidates.iter().filter(|c| c.contains('/') == qualified);
+
         if pattern == "*" {
-            // Install all available tools
-            for tool_name in manifest.tools.keys() {
-                tools_to_install.push(tool_name.clone());
+            // Select all candidates
+            for tool_name in candidates {
+                tool_names.push(tool_name.clone());
             }
-        } else if pattern.contains('*') {
+        } else if pattern.contains('*') && !pattern.contains('@') {
             // Simple wildcard matching
-            for tool_name in manifest.tools.keys() {
+            for tool_name in candidates {
                 if wildcard_match(pattern, tool_name) {
-                    tools_to_install.push(tool_name.clone());
+                    tool_names.push(tool_name.clone());
                 }
             }
         } else {
             // Exact tool name
-            tools_to_install.push(pattern.clone());
+            tool_names.push(pattern.clone());
         }
     }
 
-    if tools_to_install.is_empty() {
-        anyhow::bail!("No tools found matching the given patterns");
+    if tool_names.is_empty() {
+        fail!(Kind::Usage, "No tools found matching the given patterns");
     }
 
     // Remove duplicates
-    tools_to_install.sort();
-    tools_to_install.dedup();
+    tool_names.sort();
+    tool_names.dedup();
 
-    println!("Processing {} tool(s)...", tools_to_install.len());
+    Ok(tool_names)
+}
 
-    let mut installed_count = 0;
-    let mut upgraded_count = 0;
-    let mut skipped_count = 0;
-    let mut failed_tools = Vec::new();
+/// Outcome for one tool of an install or upgrade run
+#[derive(Debug, Serialize)]
+pub(super) struct ToolResult {
+    pub name: String,
+    /// `installed`, `upgraded`, `up_to_date`, `held` (pinned to another version) or `failed`
+    pub status: &'static str,
+    /// Version now installed
+    pub version: Option<String>,
+    pub repo: Option<String>,
+    pub error: Option<String>,
+}
 
-    for tool_name in &tools_to_install {
-        match install_single_tool(config, &manifest, tool_name) {
-            Ok(InstallResult::Installed) => installed_count += 1,
-            Ok(InstallResult::Upgraded) => upgraded_count += 1,
-            Ok(InstallResult::Skipped) => skipped_count += 1,
-            Err(e) => {
-                eprintln!("Failed to install {}: {}", tool_name, e);
-                failed_tools.push(tool_name.clone());
-            }
+/// Results and counts reported at the end of install and upgrade runs
+#[derive(Debug, Default)]
+pub(super) struct Summary {
+    pub installed: usize,
+    pub upgraded: usize,
+    pub skipped: usize,
+    pub held: Vec<String>,
+    pub failed: Vec<String>,
+    pub results: Vec<ToolResult>,
+}
+
+#[derive(Serialize)]
+struct SummaryDocument<'a> {
+    results: &'a [ToolResult],
+    summary: Counts,
+}
+
+#[derive(Serialize)]
+struct Counts {
+    installed: usize,
+    upgraded: usize,
+    up_to_date: usize,
+    held: usize,
+    failed: usize,
+}
+
+impl Summary {
+    pub fn fail(&mut self, name: &str, error: String) {
+        self.failed.push(name.to_string());
+        self.results.push(ToolResult {
+            name: name.to_string(),
+            status: "failed",
+            version: None,
+            repo: None,
+            error: Some(error),
+        });
+    }
+
+    /// Print the summary in the selected output format
+    pub fn emit(&self) {
+        match output::format() {
+            OutputFormat::Text => self.print(),
+            OutputFormat::Json => output::emit_json(&SummaryDocument {
+                results: &self.results,
+                summary: Counts {
+                    installed: self.installed,
+                    upgraded: self.upgraded,
+                    up_to_date: self.skipped,
+                    held: self.held.len(),
+                    failed: self.failed.len(),
+                },
+            }),
+            OutputFormat::Tsv => output::emit_tsv(
+                &["name", "status", "version", "repo", "error"],
+                &self
+                    .results
+                    .iter()
+                    .map(|r| {
+                        vec![
+                            r.name.clone(),
+                            r.status.to_string(),
+                            r.version.clone().unwrap_or_default(),
+                            r.repo.clone().unwrap_or_default(),
+                            r.error.clone().unwrap_or_default(),
+                        ]
+                    })
+                    .collect::<Vec<_>>(),
+            ),
         }
     }
 
-    println!("\nSummary:");
-    if installed_count > 0 {
-        println!("  Installed: {}", installed_count);
+    fn print(&self) {
+        println!("\nSummary:");
+        if self.installed > 0 {
+            println!("  Installed: {}", self.installed);
+        }
+        if self.upgraded > 0 {
+            println!("  Upgraded: {}", self.upgraded);
+        }
+        if self.skipped > 0 {
+            println!("  Up to date: {}", self.skipped);
+        }
+        if !self.held.is_empty() {
+            println!(
+                "  Held back by pins: {} ({})",
+                self.held.len(),
+                self.held.join(", ")
+            );
+        }
+        if !self.failed.is_empty() {
+            println!(
+                "  Failed: {} ({})",
+                self.failed.len(),
+                self.failed.join(", ")
+            );
+        }
     }
-    if upgraded_count > 0 {
-        println!("  Upgraded: {}", upgraded_count);
+}
+
+pub(super) fn install_tools(
+    config: &Config,
+    catalog: &Catalog,
+    db: &mut InstalledDb,
+    tool_names: &[String],
+    force: bool,
+    channel: Option<&str>,
+    scope: Scope,
+) -> Summary {
+    say!("Processing {} tool(s)...", tool_names.len());
+
+    let mut summary = Summary::default();
+    let plan = dependencies::plan(config, catalog, db, tool_names, scope);
+    if !plan.added.is_empty() {
+        say!(
+            "Also installing {} dependency(ies): {}",
+            plan.added.len(),
+            plan.added.join(", ")
+        );
     }
-    if skipped_count > 0 {
-        println!("  Up to date: {}", skipped_count);
+    for (spec, e) in &plan.failed {
+        eprintln!("Failed to install {}: {:#}", spec, e);
+        summary.fail(spec, format!("{:#}", e));
     }
-    if !failed_tools.is_empty() {
-        println!(
-            "  Failed: {} ({})",
-            failed_tools.len(),
-            failed_tools.join(", ")
-        );
+
+    let mut download_errors = prefetch(config, catalog, db, &plan.order, tool_names, channel);
+    let mut failed_names: Vec<&str> = Vec::new();
+
+    // Placement stays sequential; downloads are already verified in the artifact cache
+    for spec in &plan.order {
+        let failed_dependency = plan
+            .needs
+            .get(tool_name(spec))
+            .and_then(|needs| needs.iter().find(|n| failed_names.contains(&n.as_str())));
+        if let Some(dependency) = failed_dependency {
+            eprintln!(
+                "Failed to install {}: dependency '{}' could not be installed",
+                spec, dependency
+            );
+            summary.fail(
+                spec,
+                format!("dependency '{}' could not be installed", dependency),
+            );
+            failed_names.push(tool_name(spec));
+            continue;
+        }
+
+        if let Some(e) = download_errors.remove(spec) {
+            eprintln!("Failed to install {}: {:#}", spec, e);
+            summary.fail(spec, format!("{:#}", e));
+            failed_names.push(tool_name(spec));
+            continue;
+        }
+
+        let channel = channel_for(spec, tool_names, channel);
+        let status = match install_single_tool(config, catalog, db, spec, force, channel) {
+            Ok(InstallResult::Installed) => {
+                summary.installed += 1;
+                "installed"
+            }
+            Ok(InstallResult::Upgraded) => {
+                summary.upgraded += 1;
+                "upgraded"
+            }
+            Ok(InstallResult::Skipped) => {
+                summary.skipped += 1;
+                "up_to_date"
+            }
+            Ok(InstallResult::Held) => {
+                summary.held.push(tool_name(spec).to_string());
+                "held"
+            }
+            Err(e) => {
+                eprintln!("Failed to install {}: {:#}", spec, e);
+                summary.fail(spec, format!("{:#}", e));
+                failed_names.push(tool_name(spec));
+                continue;
+            }
+        };
+
+        let name = tool_name(spec);
+        let record = db.get(name);
+        summary.results.push(ToolResult {
+            name: name.to_string(),
+            status,
+            version: record.map(|t| t.version.clone()),
+            repo: record.map(|t| t.repo.clone()),
+            error: None,
+        });
     }
 
-    // Check if PATH is configured (only show once)
-    if installed_count > 0 || upgraded_count > 0 {
-        if let Ok(path_env) = std::env::var("PATH") {
-            let install_dir_str = config.install_dir.to_string_lossy();
-            if !path_env.contains(&install_dir_str.to_string()) {
-                println!("\nWarning: Install directory not in PATH");
-                println!("Run 'gpm setup-path' to configure your shell automatically");
+    summary
+}
+
+/// Download every artifact the run will need, `config.jobs` at a time
+///
+/// Returns the download failures by tool name. With a single job, or only one
+/// download needed, nothing is fetched here and each install downloads its own.
+fn prefetch(
+    config: &Config,
+    catalog: &Catalog,
+    db: &InstalledDb,
+    tool_names: &[String],
+    explicit: &[String],
+    channel: Option<&str>,
+) -> HashMap<String, anyhow::Error> {
+    let mut pending: Vec<Download> = tool_names
+        .iter()
+        .filter_map(|spec| {
+            let name = tool_name(spec);
+            let (base, requested) = split_version(spec);
+            let requested = requested.map(Constraint::parse).transpose().ok()?;
+            let pin = db.pin(name).ok()?;
+            let resolved = catalog.resolve(config, base, db.get(name)).ok()?;
+            let latest = resolved.tool.on_channel(self::channel(
+                channel_for(spec, explicit, channel),
+                db.get(name),
+                resolved.repo,
+            ));
+            let tool =
+                choose_release(config, name, &latest, requested.as_ref(), pin.as_ref()).ok()??;
+            let artifact = tool.artifact_for(&config.targets)?;
+            let constrained = requested.is_some() || pin.is_some();
+            let current = db
+                .get(name)
+                .is_some_and(|installed| is_current(config, installed, &tool, constrained));
+            if current {
+                return None;
             }
-        }
+            Some(Download {
+                key: spec.clone(),
+                urls: mirrors::candidates(config, resolved.repo, &artifact.url, &artifact.mirrors),
+                sha256: artifact.sha256,
+            })
+        })
+        .collect();
+
+    // Tools sharing an artifact must not write the same partial download concurrently
+    pending.sort_by(|a, b| a.sha256.cmp(&b.sha256));
+    pending.dedup_by(|a, b| a.sha256 == b.sha256);
+
+    if config.jobs < 2 || pending.len() < 2 {
+        return HashMap::new();
     }
 
-    if !failed_tools.is_empty() {
-        anyhow::bail!("Some tools failed to install");
+    downloader::download_all(config, &pending)
+        .into_iter()
+        .map(|(name, e)| (name, e.context("Failed to download tool")))
+        .collect()
+}
+
+/// Whether an installed tool is exactly the build `tool` provides for this host
+pub(super) fn is_installed_build(config: &Config, installed: &InstalledTool, tool: &Tool) -> bool {
+    let same_version = version::compare(&installed.version, &tool.version) == Ordering::Equal;
+    match tool.artifact_for(&config.targets) {
+        Some(artifact) => same_version && installed.sha256 == artifact.sha256,
+        None => same_version,
     }
+}
+
+/// Whether an installed tool needs no upgrade to `tool`: it is that build, or newer
+pub(super) fn is_up_to_date(config: &Config, installed: &InstalledTool, tool: &Tool) -> bool {
+    is_installed_build(config, installed, tool)
+        || version::compare(&installed.version, &tool.version) == Ordering::Greater
+}
+
+/// Whether installing `tool` would change nothing
+///
+/// A release chosen by a version constraint or pin must be installed exactly,
+/// even if that means going back to an older version.
+pub(super) fn is_current(
+    config: &Config,
+    installed: &InstalledTool,
+    tool: &Tool,
+    constrained: bool,
+) -> bool {
+    if constrained {
+        is_installed_build(config, installed, tool)
+    } else {
+        is_up_to_date(config, installed, tool)
+    }
+}
+
+/// The release to install: the newest one matching the requested versions and
+/// the pin, or the current release when there is neither
+///
+/// Returns `None` when the pin rules out every release with a build for this host.
+fn choose_release<'a>(
+    config: &Config,
+    tool_name: &str,
+    latest: &'a Tool,
+    requested: Option<&Constraint>,
+    pin: Option<&Constraint>,
+) -> Result<Option<Cow<'a, Tool>>> {
+    let constraints: Vec<&Constraint> = requested.into_iter().chain(pin).collect();
+    if constraints.is_empty() {
+        return Ok(Some(Cow::Borrowed(latest)));
+    }
+    if let Some(release) = latest.select(&constraints, &config.targets) {
+        return Ok(Some(release));
+    }
+
+    let Some(requested) = requested else {
+        return Ok(None);
+    };
+    if let Some(pin) = pin {
+        if latest.select(&[requested], &config.targets).is_some() {
+            fail!(
+                Kind::Usage,
+                "'{}' is pinned to {}; unpin it to install {}",
+                tool_name,
+                pin,
+                requested
+            );
+        }
+    }
+    let published: Vec<String> = latest
+        .releases()
+        .iter()
+        .filter(|release| release.artifact_for(&config.targets).is_some())
+        .map(|release| release.version.clone())
+        .collect();
+    fail!(
+        Kind::NotFound,
+        "No release of '{}' matching {} has a build for this host (available: {})",
+        tool_name,
+        requested,
+        if published.is_empty() {
+            "none".to_string()
+        } else {
+            published.join(", ")
+        }
+    );
+}
 
+/// The channel a tool follows: the one asked for now, the one it was
+/// installed with, or its repository's
+pub(super) fn channel<'a>(
+    requested: Option<&'a str>,
+    installed: Option<&'a InstalledTool>,
+    repo: &'a Repo,
+) -> &'a str {
+    requested
+        .or(installed.and_then(|t| t.channel.as_deref()))
+        .or(repo.channel.as_deref())
+        .unwrap_or(STABLE_CHANNEL)
+}
+
+/// `--channel` applies to the tools asked for, not to their dependencies
+fn channel_for<'a>(spec: &str, explicit: &[String], channel: Option<&'a str>) -> Option<&'a str> {
+    channel.filter(|_| explicit.iter().any(|e| e == spec))
+}
+
+/// Record that an installed tool follows `channel` from now on
+fn follow_channel(
+    config: &Config,
+    db: &mut InstalledDb,
+    tool_name: &str,
+    channel: &str,
+) -> Result<()> {
+    if let Some(installed) = db.tools.get_mut(tool_name) {
+        installed.channel = Some(channel.to_string());
+        db.save(config)?;
+        say!("{} now follows the {} channel", tool_name, channel);
+    }
     Ok(())
 }
 
+/// Split a spec into its `name` or `repo/name` part and the version constraint after `@`
+pub(super) fn split_version(spec: &str) -> (&str, Option<&str>) {
+    match spec.split_once('@') {
+        Some((name, constraint)) => (name, Some(constraint)),
+        None => (spec, None),
+    }
+}
+
+/// The tool name in a `name`, `repo/name` or `name@version` spec
+pub(super) fn tool_name(spec: &str) -> &str {
+    let (spec, _) = split_version(spec);
+    spec.split_once('/').map_or(spec, |(_, name)| name)
+}
+
 fn wildcard_match(pattern: &str, text: &str) -> bool {
     let pattern_chars: Vec<char> = pattern.chars().collect();
     let text_chars: Vec<char> = text.chars().collect();
@@ -144,114 +563,320 @@ enum InstallResult {
     Installed,
     Upgraded,
     Skipped,
+    Held,
 }
 
 fn install_single_tool(
     config: &Config,
-    manifest: &crate::manifest::Manifest,
-    tool_name: &str,
+    catalog: &Catalog,
+    db: &mut InstalledDb,
+    spec: &str,
+    force: bool,
+    channel: Option<&str>,
 ) -> Result<InstallResult> {
-    let tool = manifest::find_tool(manifest, tool_name)
-        .ok_or_else(|| anyhow::anyhow!("Tool '{}' not found in manifest", tool_name))?;
+    let (base, requested) = split_version(spec);
+    let requested = requested.map(Constraint::parse).transpose()?;
+    let tool_name = self::tool_name(spec);
+    let resolved = catalog.resolve(config, base, db.get(tool_name))?;
+    let following = self::channel(channel, db.get(tool_name), resolved.repo).to_string();
+    let latest = resolved.tool.on_channel(&following);
+    let latest: &Tool = &latest;
+    let pin = db.pin(tool_name)?;
+    // `--channel` is remembered, also when there is nothing to install
+    let subscribe = channel.filter(|c| {
+        db.get(tool_name)
+            .is_some_and(|t| t.channel.as_deref() != Some(*c))
+    });
+
+    let Some(tool) = choose_release(config, tool_name, latest, requested.as_ref(), pin.as_ref())?
+    else {
+        say!(
+            "Holding {} (pinned to {}, which no release in {} matches; it has v{})",
+            tool_name,
+            pin.map(|p| p.to_string()).unwrap_or_default(),
+            resolved.repo.name,
+            latest.version
+        );
+        return Ok(InstallResult::Held);
+    };
+    let tool: &Tool = &tool;
+    let constrained = requested.is_some() || pin.is_some();
+    // A pin keeping the tool below the current release holds it back
+    let held = pin.is_some() && version::compare(&tool.version, &latest.version) != Ordering::Equal;
+
+    let artifact = tool.artifact_for(&config.targets).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Tool '{}' has no build for this host ({})",
+            tool_name,
+            config.targets.join(", ")
+        )
+    })?;
 
     let dest = config.install_dir.join(tool_name);
-
-    // Check if already installed and compare versions
-    let is_upgrade = if dest.exists() {
-        // Try to get current version
-        if let Ok(current_version) = get_installed_version(&dest) {
-            if current_version == tool.version {
-                println!(
-                    "Tool '{}' v{} is already up to date",
-                    tool_name, tool.version
+    let location = match &artifact.archive {
+        Some(_) => config.package_dir(tool_name),
+        None => dest.clone(),
+    };
+    let present = location.exists();
+
+    // Check if already installed and compare against the installed-state record
+    // Going back to an older release is reported as such, but counts as an upgrade
+    let mut downgrade = false;
+    // How a tracked tool changes, and the version it had
+    let mut change = None;
+    let is_upgrade = if present {
+        match db.get(tool_name) {
+            Some(installed) if installed.repo != resolved.repo.name => {
+                say!(
+                    "Switching {} from repository {} to {}",
+                    tool_name,
+                    installed.repo,
+                    resolved.repo.name
                 );
-                return Ok(InstallResult::Skipped);
-            } else {
-                println!(
-                    "Upgrading {} from v{} to v{}",
-                    tool_name, current_version, tool.version
+            }
+            Some(installed) if is_current(config, installed, tool, constrained) => {
+                let result = if held {
+                    say!(
+                        "Holding {} at v{} (pinned to {}; {} has v{})",
+                        tool_name,
+                        installed.version,
+                        pin.map(|p| p.to_string()).unwrap_or_default(),
+                        resolved.repo.name,
+                        latest.version
+                    );
+                    InstallResult::Held
+                } else {
+                    say!(
+                        "Tool '{}' v{} is already up to date",
+                        tool_name,
+                        installed.version
+                    );
+                    InstallResult::Skipped
+                };
+                if let Some(channel) = subscribe {
+                    follow_channel(config, db, tool_name, channel)?;
+                }
+                return Ok(result);
+            }
+            Some(installed) => {
+                downgrade =
+                    version::compare(&tool.version, &installed.version) == Ordering::Less;
+                let label = match version::compare(&tool.version, &installed.version) {
+                    Ordering::Greater => "Upgrading",
+                    Ordering::Less => "Downgrading",
+                    Ordering::Equal => "Rebuilding",
+                };
+                change = Some((label, installed.version.clone()));
+            }
+            // Never overwrite a file gpm did not put there unless told to
+            None if !force => anyhow::bail!(
+                "{:?} was not installed by gpm; track it with 'gpm adopt {}' or replace it with --force",
+                location,
+                tool_name
+            ),
+            None => {
+                say!(
+                    "Replacing {} with v{} (not tracked by gpm)",
+                    tool_name,
+                    tool.version
                 );
             }
-        } else {
-            println!(
-                "Reinstalling {} v{} (version check failed)",
-                tool_name, tool.version
-            );
         }
-
-        // Remove existing binary for upgrade
-        fs::remove_file(&dest).context("Failed to remove existing binary")?;
         true
     } else {
         false
     };
 
-    let action = if is_upgrade {
-        "Upgrading"
+    let (action, versions) = match change {
+        Some((label, previous)) => (label, format!("v{} -> v{}", previous, tool.version)),
+        None if is_upgrade => ("Upgrading", format!("v{}", tool.version)),
+        None => ("Installing", format!("v{}", tool.version)),
+    };
+    let on_channel = if following == STABLE_CHANNEL {
+        String::new()
     } else {
-        "Installing"
+        format!(" ({} channel)", following)
     };
-    println!("{} {} v{}", action, tool_name, tool.version);
-    println!("  License: {}", tool.license);
-    println!("  Size: {} bytes", tool.size);
-
-    // Download with retry and verification
-    downloader::download_with_retry(&tool.url, &dest, &tool.sha256)
+    say!(
+        "{} {} {} from {}{}",
+        action,
+        tool_name,
+        versions,
+        resolved.repo.name,
+        on_channel
+    );
+    say!("  License: {}", tool.license);
+    say!("  Size: {} bytes", artifact.size);
+
+    let old_files = db
+        .get(tool_name)
+        .map(|installed| installed.files.clone())
+        .unwrap_or_default();
+    let recorded_channel = channel
+        .or(db.get(tool_name).and_then(|t| t.channel.as_deref()))
+        .map(str::to_string);
+
+    // Fetch into the artifact cache; nothing installed is touched until this verifies
+    let urls = mirrors::candidates(config, resolved.repo, &artifact.url, &artifact.mirrors);
+    let cached = downloader::download_with_retry(config, &urls, &artifact.sha256, None)
         .context("Failed to download tool")?;
 
-    // Make executable
-    let metadata = fs::metadata(&dest)?;
-    let mut permissions = metadata.permissions();
-    permissions.set_mode(0o755);
-    fs::set_permissions(&dest, permissions)?;
+    let files = match &artifact.archive {
+        Some(layout) => {
+            // Archive the outgoing version while its files are still the ones installed
+            let current = db.get(tool_name).cloned();
+            versions::archive_current(config, db, tool_name, &artifact.sha256)?;
+            archive::install_package(config, tool_name, &cached, layout, current.as_ref(), force)?
+        }
+        None => {
+            let staged = versions::staging_path(&dest);
+            if let Err(e) = fs::copy(&cached, &staged) {
+                let _ = fs::remove_file(&staged);
+                return Err(e).context("Failed to stage binary");
+            }
+
+            // Keep the outgoing version for rollback, then swap the new one in
+            versions::archive_current(config, db, tool_name, &artifact.sha256)?;
+            versions::swap_into_place(&staged, &dest)?;
+            archive::remove_files_except(&old_files, std::slice::from_ref(&dest))?;
+            Vec::new()
+        }
+    };
+
+    db.record(
+        tool_name,
+        InstalledTool {
+            version: tool.version.clone(),
+            sha256: artifact.sha256,
+            url: artifact.url,
+            installed_at: state::now_unix(),
+            repo: resolved.repo.name.clone(),
+            repo_version: resolved.repo_version.to_string(),
+            size: fs::metadata(&cached).ok().map(|m| m.len()),
+            archive: artifact.archive,
+            files,
+            channel: recorded_channel,
+            depends: tool
+                .depends
+                .iter()
+                .map(|dependency| self::tool_name(dependency).to_string())
+                .collect(),
+        },
+    );
+    db.save(config)?;
 
     let result = if is_upgrade {
         InstallResult::Upgraded
     } else {
         InstallResult::Installed
     };
-    let action_past = if is_upgrade { "upgraded" } else { "installed" };
-    println!("Successfully {} {} to {:?}", action_past, tool_name, dest);
+    let action_past = if downgrade {
+        "downgraded"
+    } else if is_upgrade {
+        "upgraded"
+    } else {
+        "installed"
+    };
+    say!(
+        "Successfully {} {} to {:?}",
+        action_past,
+        tool_name,
+        location
+    );
     Ok(result)
 }
 
-fn get_installed_version(binary_path: &std::path::Path) -> Result<String> {
-    use std::process::Command;
-
-    // Try common version flags
-    let version_flags = ["--version", "-V", "-v"];
-
-    for flag in &version_flags {
-        if let Ok(output) = Command::new(binary_path).arg(flag).output() {
-            if output.status.success() {
-                let version_output = String::from_utf8_lossy(&output.stdout);
-                // Extract version number (first sequence of digits and dots)
-                if let Some(version) = extract_version_number(&version_output) {
-                    return Ok(version);
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::rollback;
+    use crate::platform::DEFAULT_TARGET;
+    use serde_json::json;
+
+    fn installed(version: &str, sha256: &str) -> InstalledTool {
+        InstalledTool {
+            version: version.to_string(),
+            sha256: sha256.to_string(),
+            url: String::new(),
+            installed_at: 0,
+            repo: "main".to_string(),
+            repo_version: "1.0.0".to_string(),
+            size: None,
+            archive: None,
+            files: Vec::new(),
+            channel: None,
+            depends: Vec::new(),
         }
     }
 
-    anyhow::bail!("Could not determine version")
-}
+    #[test]
+    fn a_pinned_release_must_be_installed_exactly() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(dir.path());
+        let catalog = Catalog::for_tests(&[(
+            "main",
+            json!({"jq": {
+                "version": "1.6",
+                "targets": {DEFAULT_TARGET: {"url": "https://example.invalid/jq", "sha256": "a"}},
+            }}),
+        )]);
+        let tool = &catalog.repos[0].manifest.tools["jq"];
+
+        // Newer than the release: fine unless a pin asks for that release
+        let newer = installed("1.7", "b");
+        assert!(is_current(&config, &newer, tool, false));
+        assert!(!is_current(&config, &newer, tool, true));
+
+        let same = installed("1.6", "a");
+        assert!(is_current(&config, &same, tool, true));
+        assert!(!is_current(
+            &config,
+            &installed("1.6", "rebuilt"),
+            tool,
+            true
+        ));
+        assert!(!is_current(&config, &installed("1.5", "c"), tool, false));
+    }
 
-fn extract_version_number(text: &str) -> Option<String> {
-    // Simple version extraction - look for first number.number pattern
-    for line in text.lines() {
-        for word in line.split_whitespace() {
-            let cleaned = word.trim_start_matches('v');
-            if cleaned.chars().next()?.is_ascii_digit() && cleaned.contains('.') {
-                // Basic validation - starts with digit and contains dot
-                let version_part: String = cleaned
-                    .chars()
-                    .take_while(|c| c.is_ascii_digit() || *c == '.' || *c == '-')
-                    .collect();
-                if !version_part.is_empty() {
-                    return Some(version_part);
-                }
-            }
-        }
+    #[test]
+    fn upgrading_a_binary_to_a_package_keeps_the_binary_for_rollback() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(dir.path());
+
+        // An adopted binary, never downloaded into the artifact cache
+        let binary = config.install_dir.join("pk");
+        fs::write(&binary, "old binary").unwrap();
+        let old_sha256 = downloader::sha256_file(&binary).unwrap();
+        let mut db = InstalledDb::default();
+        db.record("pk", installed("1.0", &old_sha256));
+
+        let package = archive::package_for_tests(dir.path());
+        let sha256 = downloader::sha256_file(&package).unwrap();
+        fs::create_dir_all(config.artifacts_dir()).unwrap();
+        fs::copy(&package, config.artifact_path(&sha256)).unwrap();
+        let catalog = Catalog::for_tests(&[(
+            "main",
+            json!({"pk": {
+                "version": "2.0",
+                "targets": {DEFAULT_TARGET: {
+                    "url": "pk.tar.gz",
+                    "sha256": sha256,
+                    "archive": {"format": "tar.gz", "bin": ["bin/pk"]},
+                }},
+            }}),
+        )]);
+
+        install_single_tool(&config, &catalog, &mut db, "pk", false, None).unwrap();
+        assert_eq!(
+            fs::read_to_string(config.artifact_path(&old_sha256)).unwrap(),
+            "old binary"
+        );
+        assert_eq!(db.history["pk"][0].sha256, old_sha256);
+
+        rollback::run(&config, "pk").unwrap();
+        let db = InstalledDb::load(&config).unwrap();
+        assert_eq!(db.get("pk").unwrap().version, "1.0");
+        assert!(!fs::symlink_metadata(&binary).unwrap().is_symlink());
+        assert_eq!(fs::read_to_string(&binary).unwrap(), "old binary");
     }
-    None
 }
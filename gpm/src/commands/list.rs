@@ -1,34 +1,161 @@
 use anyhow::Result;
+use serde::Serialize;
 use std::fs;
+use std::path::PathBuf;
 
 use crate::config::Config;
+use crate::output::{self, OutputFormat};
+use crate::state::InstalledDb;
+
+/// One installed tool, as printed by `gpm list --output json`
+#[derive(Serialize)]
+struct Entry {
+    name: String,
+    /// `binary`, `package`, `missing` (recorded but gone from disk) or
+    /// `untracked` (in the install dir but not installed by gpm)
+    status: &'static str,
+    version: Option<String>,
+    repo: Option<String>,
+    sha256: Option<String>,
+    /// Seconds since the Unix epoch
+    installed_at: Option<u64>,
+    path: PathBuf,
+    /// Bytes on disk, for binaries
+    size: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Document {
+    install_dir: PathBuf,
+    tools: Vec<Entry>,
+}
 
 pub fn run(config: &Config) -> Result<()> {
     let entries = fs::read_dir(&config.install_dir)?;
+    let db = InstalledDb::load(config)?;
 
-    println!("Installed tools in {:?}:\n", config.install_dir);
-    println!("{:<20}\t{}", "NAME", "SIZE");
-    println!("{}", "-".repeat(40));
-
-    let mut found_any = false;
+    let mut tools = Vec::new();
 
     for entry in entries {
         let entry = entry?;
         let path = entry.path();
 
-        if path.is_file() {
+        // Links into package prefixes are listed under their package
+        if path.is_file() && !db.owns_file(&path) {
             let metadata = fs::metadata(&path)?;
-            let name = path.file_name().unwrap().to_string_lossy();
-            let size = metadata.len();
+            let name = path.file_name().unwrap().to_string_lossy().to_string();
+            let record = db.get(&name);
 
-            println!("{:<20}\t{} bytes", name, size);
-            found_any = true;
+            tools.push(Entry {
+                status: if record.is_some() {
+                    "binary"
+                } else {
+                    "untracked"
+                },
+                version: record.map(|t| t.version.clone()),
+                repo: record.map(|t| t.repo.clone()),
+                sha256: record.map(|t| t.sha256.clone()),
+                installed_at: record.map(|t| t.installed_at),
+                path,
+                size: Some(metadata.len()),
+                name,
+            });
         }
     }
 
-    if !found_any {
-        println!("No tools installed");
+    // Packages, and tools recorded as installed whose binary is no longer on disk
+    for (name, tool) in &db.tools {
+        let path = match tool.archive {
+            Some(_) => config.package_dir(name),
+            None => config.install_dir.join(name),
+        };
+        let status = match (&tool.archive, path.exists()) {
+            (Some(_), true) => "package",
+            (_, false) => "missing",
+            (None, true) => continue,
+        };
+
+        tools.push(Entry {
+            name: name.clone(),
+            status,
+            version: Some(tool.version.clone()),
+            repo: Some(tool.repo.clone()),
+            sha256: Some(tool.sha256.clone()),
+            installed_at: Some(tool.installed_at),
+            path,
+            size: None,
+        });
+    }
+
+    tools.sort_by(|a, b| a.name.cmp(&b.name));
+
+    match output::format() {
+        OutputFormat::Text => print_table(config, &tools),
+        OutputFormat::Json => output::emit_json(&Document {
+            install_dir: config.install_dir.clone(),
+            tools,
+        }),
+        OutputFormat::Tsv => output::emit_tsv(
+            &[
+                "name",
+                "status",
+                "version",
+                "repo",
+                "sha256",
+                "installed_at",
+                "path",
+                "size",
+            ],
+            &tools
+                .iter()
+                .map(|t| {
+                    vec![
+                        t.name.clone(),
+                        t.status.to_string(),
+                        t.version.clone().unwrap_or_default(),
+                        t.repo.clone().unwrap_or_default(),
+                        t.sha256.clone().unwrap_or_default(),
+                        t.installed_at.map(|v| v.to_string()).unwrap_or_default(),
+                        t.path.display().to_string(),
+                        t.size.map(|v| v.to_string()).unwrap_or_default(),
+                    ]
+                })
+                .collect::<Vec<_>>(),
+        ),
     }
 
     Ok(())
 }
+
+fn print_table(config: &Config, tools: &[Entry]) {
+    let (untracked, managed): (Vec<&Entry>, Vec<&Entry>) =
+        tools.iter().partition(|t| t.status == "untracked");
+
+    println!("Installed tools in {:?}:\n", config.install_dir);
+    println!("{:<20}\t{:<12}\tSIZE", "NAME", "VERSION");
+    println!("{}", "-".repeat(50));
+
+    for tool in &managed {
+        let version = tool.version.as_deref().unwrap_or("unknown");
+        match tool.size {
+            Some(size) => println!("{:<20}\t{:<12}\t{} bytes", tool.name, version, size),
+            None => println!("{:<20}\t{:<12}\t{}", tool.name, version, tool.status),
+        }
+    }
+
+    if managed.is_empty() {
+        println!("No tools installed");
+    }
+
+    if !untracked.is_empty() {
+        println!("\nUntracked files (not installed by gpm, see 'gpm adopt'):\n");
+        for tool in &untracked {
+            println!(
+                "{:<20}\t{:<12}\t{} bytes",
+                tool.name,
+                "-",
+                tool.size.unwrap_or(0)
+            );
+        }
+    }
+}
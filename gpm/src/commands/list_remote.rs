@@ -1,26 +1,167 @@
 use anyhow::Result;
+use serde::Serialize;
 
 use crate::config::Config;
 use crate::manifest;
+use crate::output::{self, OutputFormat};
+
+/// A loaded repository, as printed by `gpm list-remote --output json`
+#[derive(Serialize)]
+struct RepoEntry {
+    name: String,
+    url: String,
+    priority: i64,
+    repo_version: String,
+    serial: Option<u64>,
+}
+
+/// One tool of one repository
+#[derive(Serialize)]
+struct ToolEntry {
+    name: String,
+    version: String,
+    repo: String,
+    license: String,
+    description: String,
+    /// Whether the repository has a build for this host
+    available: bool,
+    /// Higher-priority repository a bare `gpm install <name>` would pick instead
+    shadowed_by: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Document {
+    repos: Vec<RepoEntry>,
+    tools: Vec<ToolEntry>,
+}
 
 pub fn run(config: &Config) -> Result<()> {
-    let manifest = manifest::fetch_manifest(config)?;
+    let catalog = manifest::fetch_catalog(config)?;
+
+    let mut entries: Vec<_> = catalog
+        .repos
+        .iter()
+        .enumerate()
+        .flat_map(|(rank, r)| {
+            r.manifest
+                .tools
+                .iter()
+                .map(move |(name, tool)| (name, rank, r, tool))
+        })
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0).then(a.1.cmp(&b.1)));
 
-    println!("Available tools (Manifest v{}):\n", manifest.repo_version);
+    let tools: Vec<ToolEntry> = entries
+        .into_iter()
+        .map(|(name, _, r, tool)| {
+            let available = tool.artifact_for(&config.targets).is_some();
+            let shadowed_by = catalog
+                .preferred(config, name)
+                .map(|preferred| preferred.repo.name.clone())
+                .filter(|preferred| available && *preferred != r.repo.name);
+            ToolEntry {
+                name: name.clone(),
+                version: tool.version.clone(),
+                repo: r.repo.name.clone(),
+                license: tool.license.clone(),
+                description: tool.description.clone(),
+                available,
+                shadowed_by,
+            }
+        })
+        .collect();
+
+    let repos: Vec<RepoEntry> = catalog
+        .repos
+        .iter()
+        .map(|r| RepoEntry {
+            name: r.repo.name.clone(),
+            url: r.repo.url.clone(),
+            priority: r.repo.priority,
+            repo_version: r.manifest.repo_version.clone(),
+            serial: r.manifest.serial,
+        })
+        .collect();
+
+    match output::format() {
+        OutputFormat::Text => print_table(config, &repos, &tools),
+        OutputFormat::Json => output::emit_json(&Document { repos, tools }),
+        OutputFormat::Tsv => output::emit_tsv(
+            &[
+                "name",
+                "version",
+                "repo",
+                "license",
+                "available",
+                "shadowed_by",
+                "description",
+            ],
+            &tools
+                .iter()
+                .map(|t| {
+                    vec![
+                        t.name.clone(),
+                        t.version.clone(),
+                        t.repo.clone(),
+                        t.license.clone(),
+                        t.available.to_string(),
+                        t.shadowed_by.clone().unwrap_or_default(),
+                        t.description.clone(),
+                    ]
+                })
+                .collect::<Vec<_>>(),
+        ),
+    }
+
+    Ok(())
+}
+
+fn print_table(config: &Config, repos: &[RepoEntry], tools: &[ToolEntry]) {
+    let repos: Vec<String> = repos
+        .iter()
+        .map(|r| format!("{} v{}", r.name, r.repo_version))
+        .collect();
+    println!("Available tools ({}):\n", repos.join(", "));
     println!(
-        "{:<15}\t{:<10}\t{:<15}\t{}",
-        "NAME", "VERSION", "LICENSE", "DESCRIPTION"
+        "{:<15}\t{:<10}\t{:<12}\t{:<15}\tDESCRIPTION",
+        "NAME", "VERSION", "REPO", "LICENSE"
     );
-    println!("{}", "-".repeat(80));
+    println!("{}", "-".repeat(96));
+
+    let mut unavailable = 0;
+    let mut shadowed = 0;
 
-    for (name, tool) in &manifest.tools {
+    for tool in tools {
+        let note = if !tool.available {
+            unavailable += 1;
+            " [unavailable for this host]".to_string()
+        } else if let Some(preferred) = &tool.shadowed_by {
+            shadowed += 1;
+            format!(" [shadowed by {}]", preferred)
+        } else {
+            String::new()
+        };
         println!(
-            "{:<15}\t{:<10}\t{:<15}\t{}",
-            name, tool.version, tool.license, tool.description
+            "{:<15}\t{:<10}\t{:<12}\t{:<15}\t{}{}",
+            tool.name, tool.version, tool.repo, tool.license, tool.description, note
         );
     }
 
-    println!("\nUse 'gpm install <name>' to install a tool");
+    if unavailable > 0 {
+        println!(
+            "\n{} tool(s) have no build for this host ({})",
+            unavailable,
+            config.targets.join(", ")
+        );
+    }
 
-    Ok(())
+    if shadowed > 0 {
+        println!(
+            "\n{} tool(s) are also provided by a higher-priority repository; \
+             use 'gpm install <repo>/<name>' to pick one explicitly",
+            shadowed
+        );
+    }
+
+    println!("\nUse 'gpm install <name>' to install a tool");
 }
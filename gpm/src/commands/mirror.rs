@@ -0,0 +1,389 @@
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::commands::dependencies::{self, Scope};
+use crate::commands::install;
+use crate::config::Config;
+use crate::downloader::{self, Download};
+use crate::manifest::{self, Manifest, Tool};
+use crate::mirrors;
+use crate::output::{fail, say, Kind};
+use crate::platform::DEFAULT_TARGET;
+use crate::signature;
+use crate::state::{self, InstalledDb};
+use crate::transport;
+use crate::version::Constraint;
+
+pub fn run(
+    config: &Config,
+    dir: &str,
+    tool_patterns: &[String],
+    targets: &[String],
+    sign_key: Option<&str>,
+    recommends: bool,
+) -> Result<()> {
+    let catalog = manifest::fetch_catalog(config)?;
+    let dir = PathBuf::from(dir);
+    fs::create_dir_all(&dir).context(format!("Failed to create {:?}", dir))?;
+
+    // Every tool of every repository, whether or not this host can run it
+    let mut candidates: Vec<String> = Vec::new();
+    for r in &catalog.repos {
+        for name in r.manifest.tools.keys() {
+            candidates.push(name.clone());
+            candidates.push(format!("{}/{}", r.repo.name, name));
+        }
+    }
+    let requested = install::expand_patterns(tool_patterns, false, &candidates, "mirror")?;
+
+    // A snapshot others install from must hold every dependency, whatever this host has
+    let plan = dependencies::plan_for(
+        config,
+        &catalog,
+        &InstalledDb::default(),
+        &requested,
+        targets,
+        if recommends {
+            Scope::Recommends
+        } else {
+            Scope::Depends
+        },
+    );
+    if !plan.added.is_empty() {
+        say!(
+            "Also mirroring {} dependency(ies): {}",
+            plan.added.len(),
+            plan.added.join(", ")
+        );
+    }
+
+    // An earlier export in the same directory is extended, not replaced
+    let manifest_path = dir.join("manifest.json");
+    let mut snapshot = if manifest_path.exists() {
+        let data =
+            fs::read(&manifest_path).context(format!("Failed to read {:?}", manifest_path))?;
+        serde_json::from_slice(&data).context(format!(
+            "{:?} exists but is not a gpm manifest",
+            manifest_path
+        ))?
+    } else {
+        let first = &catalog.repos[0].manifest;
+        Manifest {
+            repo_version: first.repo_version.clone(),
+            updated_at: first.updated_at.clone(),
+            serial: None,
+            expires: None,
+            tools: BTreeMap::new(),
+        }
+    };
+    // The snapshot is a manifest of its own; nobody re-signs it before it would expire
+    snapshot.serial = Some(state::now_unix().max(snapshot.serial.unwrap_or(0) + 1));
+    snapshot.expires = None;
+
+    let mut selected: BTreeMap<String, Tool> = BTreeMap::new();
+    let mut downloads: Vec<Download> = Vec::new();
+    let mut failed = Vec::new();
+    for (spec, e) in plan.failed {
+        eprintln!("Failed to mirror {}: {:#}", spec, e);
+        failed.push(spec);
+    }
+
+    for spec in &plan.order {
+        let name = install::tool_name(spec);
+        let (base, requested) = install::split_version(spec);
+        let requested = match requested.map(Constraint::parse).transpose() {
+            Ok(requested) => requested,
+            Err(e) => {
+                eprintln!("Failed to mirror {}: {}", spec, e);
+                failed.push(spec.clone());
+                continue;
+            }
+        };
+        let resolved = match catalog.resolve(config, base, None) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                eprintln!("Failed to mirror {}: {}", spec, e);
+                failed.push(spec.clone());
+                continue;
+            }
+        };
+
+        let mut tool = resolved.tool.clone();
+        // Older releases are only exported when asked for, as in `make@>=4.3`
+        tool.history.retain(|release| {
+            requested
+                .as_ref()
+                .is_some_and(|c| c.matches(&release.version))
+        });
+        keep_targets(&mut tool, targets);
+        if tool.url.is_none() && tool.targets.is_empty() {
+            say!("Skipping {}: no build for the selected targets", name);
+            continue;
+        }
+
+        // Resolve every URL now; the copies are named after them below
+        for (url, sha256, alternatives) in artifacts_mut(&mut tool) {
+            *url = transport::join(&resolved.repo.url, url);
+            let urls = mirrors::candidates(config, resolved.repo, url, alternatives);
+            downloads.push(Download {
+                key: sha256.clone(),
+                urls,
+                sha256: sha256.clone(),
+            });
+        }
+        selected.insert(name.to_string(), tool);
+    }
+
+    downloads.sort_by(|a, b| a.sha256.cmp(&b.sha256));
+    downloads.dedup_by(|a, b| a.sha256 == b.sha256);
+
+    say!(
+        "Mirroring {} tool(s), {} file(s), into {:?}...",
+        selected.len(),
+        downloads.len(),
+        dir
+    );
+    let download_errors = downloader::download_all(config, &downloads);
+
+    let mut file_names: HashMap<String, String> = HashMap::new();
+    for (name, mut tool) in selected {
+        let mut result = Ok(());
+
+        for (url, sha256, alternatives) in artifacts_mut(&mut tool) {
+            if let Some(e) = download_errors.get(sha256.as_str()) {
+                result = Err(anyhow::anyhow!("Failed to download {}: {:#}", url, e));
+                break;
+            }
+            match export_file(config, &dir, &mut file_names, url, sha256) {
+                Ok(file_name) => {
+                    *url = file_name;
+                    alternatives.clear();
+                }
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        match result {
+            Ok(()) => {
+                say!("Exported {} v{}", name, tool.version);
+                snapshot.tools.insert(name, tool);
+            }
+            Err(e) => {
+                eprintln!("Failed to mirror {}: {:#}", name, e);
+                failed.push(name);
+            }
+        }
+    }
+
+    write_manifest(&dir, &snapshot, sign_key)?;
+    say!(
+        "\nMirror in {:?} now holds {} tool(s)",
+        dir,
+        snapshot.tools.len()
+    );
+    say!(
+        "Serve it over HTTP or use it directly: gpm repo add <name> {:?}",
+        dir
+    );
+
+    if !failed.is_empty() {
+        failed.sort();
+        fail!(
+            Kind::Partial,
+            "Some tools failed to mirror: {}",
+            failed.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Drop builds for targets that were not asked for
+fn keep_targets(tool: &mut Tool, targets: &[String]) {
+    if targets.is_empty() {
+        return;
+    }
+
+    let keep_legacy = targets.iter().any(|t| t == DEFAULT_TARGET);
+    tool.targets.retain(|target, _| targets.contains(target));
+    if !keep_legacy {
+        tool.url = None;
+        tool.sha256 = None;
+        tool.mirrors.clear();
+    }
+
+    for release in tool.history.iter_mut().chain(tool.channels.values_mut()) {
+        release.targets.retain(|target, _| targets.contains(target));
+        if !keep_legacy {
+            release.url = None;
+            release.sha256 = None;
+            release.mirrors.clear();
+        }
+    }
+    tool.history
+        .retain(|release| release.url.is_some() || !release.targets.is_empty());
+    tool.channels
+        .retain(|_, release| release.url.is_some() || !release.targets.is_empty());
+}
+
+/// The URL, checksum and alternative URLs of every build a tool lists, other releases included
+fn artifacts_mut(tool: &mut Tool) -> Vec<(&mut String, &mut String, &mut Vec<String>)> {
+    let mut artifacts = Vec::new();
+
+    if let (Some(url), Some(sha256)) = (tool.url.as_mut(), tool.sha256.as_mut()) {
+        artifacts.push((url, sha256, &mut tool.mirrors));
+    }
+    for artifact in tool.targets.values_mut() {
+        artifacts.push((
+            &mut artifact.url,
+            &mut artifact.sha256,
+            &mut artifact.mirrors,
+        ));
+    }
+
+    for release in tool.history.iter_mut().chain(tool.channels.values_mut()) {
+        if let (Some(url), Some(sha256)) = (release.url.as_mut(), release.sha256.as_mut()) {
+            artifacts.push((url, sha256, &mut release.mirrors));
+        }
+        for artifact in release.targets.values_mut() {
+            artifacts.push((
+                &mut artifact.url,
+                &mut artifact.sha256,
+                &mut artifact.mirrors,
+            ));
+        }
+    }
+
+    artifacts
+}
+
+/// Copy a verified artifact into the mirror and return its file name there
+///
+/// Files keep the name of their original URL, so the mirror also works as a
+/// mirror base URL for the repository it came from. Clashing names get the
+/// checksum as a prefix.
+fn export_file(
+    config: &Config,
+    dir: &Path,
+    file_names: &mut HashMap<String, String>,
+    url: &str,
+    sha256: &str,
+) -> Result<String> {
+    let mut file_name = mirrors::file_name(url).unwrap_or_else(|| sha256.to_string());
+    // A file left by an earlier export with other contents may still be
+    // referenced by clients holding that manifest, so it is never replaced
+    let existing = dir.join(&file_name);
+    let kept = existing.exists()
+        && downloader::sha256_file(&existing).context(format!("Failed to read {:?}", existing))?
+            != sha256;
+    if kept
+        || file_names
+            .get(&file_name)
+            .is_some_and(|owner| owner != sha256)
+        || file_name == "manifest.json"
+        || file_name.starts_with("manifest.json.")
+    {
+        file_name = format!("{}-{}", sha256.get(..16).unwrap_or(sha256), file_name);
+    }
+    file_names.insert(file_name.clone(), sha256.to_string());
+
+    let dest = dir.join(&file_name);
+    if downloader::verify_sha256(&dest, sha256).is_ok() {
+        return Ok(file_name);
+    }
+
+    let temp = dir.join(format!(".{}.gpm-new", file_name));
+    fs::copy(config.artifact_path(sha256), &temp)
+        .context(format!("Failed to copy {} into the mirror", file_name))?;
+    fs::rename(&temp, &dest).context(format!("Failed to write {:?}", dest))?;
+
+    Ok(file_name)
+}
+
+fn write_manifest(dir: &Path, snapshot: &Manifest, sign_key: Option<&str>) -> Result<()> {
+    let manifest_path = dir.join("manifest.json");
+    let signature_path = dir.join("manifest.json.minisig");
+
+    let mut data = serde_json::to_vec_pretty(snapshot)?;
+    data.push(b'\n');
+
+    // Sign before writing anything so a bad key leaves the previous export intact
+    let signature = match sign_key {
+        Some(key) => {
+            let comment = format!(
+                "timestamp:{}\tfile:manifest.json\thashed",
+                state::now_unix()
+            );
+            Some(signature::sign(Path::new(key), &data, &comment)?)
+        }
+        None => None,
+    };
+
+    let temp = dir.join(".manifest.json.gpm-new");
+    fs::write(&temp, &data).context("Failed to write mirror manifest")?;
+    fs::rename(&temp, &manifest_path).context("Failed to write mirror manifest")?;
+
+    match signature {
+        Some(signature) => {
+            fs::write(&signature_path, signature).context("Failed to write manifest signature")?;
+            say!("Signed manifest with {}", sign_key.unwrap_or_default());
+        }
+        None => {
+            // A signature of the previous manifest would no longer match
+            if signature_path.exists() {
+                fs::remove_file(&signature_path)?;
+            }
+            say!(
+                "The rewritten manifest is unsigned; sign it with --sign-key or \
+                 'minisign -Sm {:?}'",
+                manifest_path
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_artifact(config: &Config, data: &[u8]) -> String {
+        let sha256 = downloader::sha256_reader(data).unwrap();
+        fs::create_dir_all(config.artifacts_dir()).unwrap();
+        fs::write(config.artifact_path(&sha256), data).unwrap();
+        sha256
+    }
+
+    #[test]
+    fn re_exports_never_overwrite_other_contents() {
+        let root = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(root.path());
+        let dir = root.path().join("mirror");
+        fs::create_dir_all(&dir).unwrap();
+        let url = "https://example.com/releases/tool.tar.gz";
+        let old = cache_artifact(&config, b"old release");
+        let new = cache_artifact(&config, b"new release");
+
+        let name = export_file(&config, &dir, &mut HashMap::new(), url, &old).unwrap();
+        assert_eq!(name, "tool.tar.gz");
+
+        // The same file again is left alone under its plain name
+        let name = export_file(&config, &dir, &mut HashMap::new(), url, &old).unwrap();
+        assert_eq!(name, "tool.tar.gz");
+
+        // Different contents go next to it, then are reused on the next run
+        let name = export_file(&config, &dir, &mut HashMap::new(), url, &new).unwrap();
+        assert_eq!(name, format!("{}-tool.tar.gz", &new[..16]));
+        let again = export_file(&config, &dir, &mut HashMap::new(), url, &new).unwrap();
+        assert_eq!(again, name);
+
+        assert_eq!(fs::read(dir.join("tool.tar.gz")).unwrap(), b"old release");
+        assert_eq!(fs::read(dir.join(&name)).unwrap(), b"new release");
+    }
+}
@@ -1,30 +1,120 @@
+mod adopt;
+mod config;
+mod dependencies;
+mod info;
 mod install;
 mod list;
 mod list_remote;
+mod mirror;
+mod outdated;
+mod pin;
 mod remove;
+mod repo;
+mod rollback;
+mod search;
 mod setup_path;
 mod update;
+mod upgrade;
+mod verify;
 
 use anyhow::Result;
 
 use crate::cli::{Cli, Commands};
 use crate::config::Config;
+use crate::lock::{self, LockMode};
 
 pub fn execute(cli: Cli) -> Result<()> {
-    let config = Config::new(
-        cli.install_dir,
-        cli.cache_dir,
-        cli.manifest_url,
-        cli.verbose,
-    )?;
+    // Settings commands work on the files alone and must not create any directories
+    if let Commands::Config { action } = &cli.command {
+        return config::run(action, cli.settings());
+    }
+    if let Commands::Repo { action } = &cli.command {
+        return repo::run(action, cli.settings());
+    }
+
+    let mut config = Config::new(cli.settings(), cli.verbose)?;
+    // Deliberately not a setting: it should never stay on by accident
+    config.allow_stale_manifest = cli.allow_stale_manifest;
+
+    // Held until the command finishes
+    let _lock = match lock_mode(&cli.command) {
+        Some(mode) => Some(lock::acquire(&config, mode, config.wait)?),
+        None => None,
+    };
 
     match cli.command {
-        Commands::Install { tool_names, all } => install::run(&config, &tool_names, all),
+        Commands::Install {
+            tool_names,
+            all,
+            force,
+            channel,
+            no_recommends,
+        } => install::run(
+            &config,
+            &tool_names,
+            all,
+            force,
+            channel.as_deref(),
+            !no_recommends,
+        ),
+        Commands::Adopt { file, all, force } => adopt::run(&config, file.as_deref(), all, force),
+        Commands::Info { tool_name } => info::run(&config, &tool_name),
         Commands::List => list::run(&config),
         Commands::ListRemote => list_remote::run(&config),
-        Commands::Remove { tool_names, all } => remove::run(&config, &tool_names, all),
+        Commands::Search { query } => search::run(&config, &query),
+        Commands::Remove {
+            tool_names,
+            all,
+            force,
+        } => remove::run(&config, &tool_names, all, force),
         Commands::Update => update::run(&config),
+        Commands::Outdated { tool_names } => outdated::run(&config, &tool_names),
+        Commands::Upgrade { tool_names, all } => upgrade::run(&config, &tool_names, all),
+        Commands::Rollback { tool_name } => rollback::run(&config, &tool_name),
+        Commands::Pin { tool } => pin::run(&config, tool.as_deref()),
+        Commands::Unpin { tool_name } => pin::unpin(&config, &tool_name),
+        Commands::Verify { tool_names, repair } => verify::run(&config, &tool_names, repair),
+        Commands::Mirror {
+            dir,
+            tool_names,
+            targets,
+            sign_key,
+            no_recommends,
+        } => mirror::run(
+            &config,
+            &dir,
+            &tool_names,
+            &targets,
+            sign_key.as_deref(),
+            !no_recommends,
+        ),
 
+        Commands::Config { .. } | Commands::Repo { .. } => {
+            unreachable!("handled before loading the configuration")
+        }
         Commands::SetupPath => setup_path::run(&config),
     }
 }
+
+fn lock_mode(command: &Commands) -> Option<LockMode> {
+    match command {
+        Commands::Install { .. }
+        | Commands::Adopt { .. }
+        | Commands::Upgrade { .. }
+        | Commands::Remove { .. }
+        | Commands::Rollback { .. }
+        | Commands::Pin { tool: Some(_) }
+        | Commands::Unpin { .. }
+        | Commands::Mirror { .. }
+        | Commands::Update
+        | Commands::Verify { repair: true, .. } => Some(LockMode::Exclusive),
+        Commands::Info { .. }
+        | Commands::List
+        | Commands::ListRemote
+        | Commands::Search { .. }
+        | Commands::Pin { tool: None }
+        | Commands::Outdated { .. }
+        | Commands::Verify { repair: false, .. } => Some(LockMode::Shared),
+        Commands::Config { .. } | Commands::Repo { .. } | Commands::SetupPath => None,
+    }
+}
@@ -0,0 +1,169 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+
+use crate::commands::install;
+use crate::config::Config;
+use crate::manifest;
+use crate::output::{self, say, OutputFormat};
+use crate::state::InstalledDb;
+use crate::version::{self, Constraint};
+
+/// An installed tool with a newer build available
+#[derive(Serialize)]
+struct Entry {
+    name: String,
+    current: String,
+    available: String,
+    repo: String,
+    /// Release channel the tool follows
+    channel: String,
+    /// Versions the tool is pinned to, for tools held back
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pinned: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Document {
+    outdated: Vec<Entry>,
+    /// Tools with a newer version available that their pin does not allow
+    held: Vec<Entry>,
+}
+
+pub fn run(config: &Config, tool_patterns: &[String]) -> Result<()> {
+    let catalog = manifest::fetch_catalog(config)?;
+    let db = InstalledDb::load(config)?;
+
+    let installed: Vec<String> = db.tools.keys().cloned().collect();
+    let tool_names = if installed.is_empty() {
+        Vec::new()
+    } else {
+        install::expand_patterns(tool_patterns, false, &installed, "check")?
+    };
+
+    let mut outdated = Vec::new();
+    let mut held = Vec::new();
+
+    for tool_name in &tool_names {
+        let Some(current) = db.get(tool_name) else {
+            eprintln!("Tool '{}' is not installed", tool_name);
+            continue;
+        };
+
+        let resolved = match catalog.resolve(config, tool_name, Some(current)) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                if config.verbose {
+                    say!("{}", e);
+                }
+                continue;
+            }
+        };
+        let channel = install::channel(None, Some(current), resolved.repo);
+        let latest = resolved.tool.on_channel(channel);
+        let pin = db.pin(tool_name)?;
+
+        // The newest release the pin allows, which is what 'gpm upgrade' would install
+        let allowed = match &pin {
+            Some(pin) => latest.select(&[pin], &config.targets),
+            None => Some(Cow::Borrowed(latest.as_ref())),
+        };
+        let entry = |available: &str, pinned: Option<&Constraint>| Entry {
+            name: tool_name.clone(),
+            current: current.version.clone(),
+            available: available.to_string(),
+            repo: resolved.repo.name.clone(),
+            channel: channel.to_string(),
+            pinned: pinned.map(|p| p.to_string()),
+        };
+
+        // A pin below the installed version counts too: upgrade goes back to it
+        match allowed {
+            Some(release) if !install::is_current(config, current, &release, pin.is_some()) => {
+                outdated.push(entry(&release.version, None));
+            }
+            _ => {
+                if let Some(pin) = pin.as_ref().filter(|_| {
+                    version::compare(&latest.version, &current.version) == Ordering::Greater
+                }) {
+                    held.push(entry(&latest.version, Some(pin)));
+                }
+            }
+        }
+    }
+
+    match output::format() {
+        OutputFormat::Text => print_table(&db, &outdated, &held),
+        OutputFormat::Json => output::emit_json(&Document { outdated, held }),
+        OutputFormat::Tsv => output::emit_tsv(
+            &["name", "current", "available", "repo", "channel", "pinned"],
+            &outdated
+                .iter()
+                .chain(&held)
+                .map(|e| {
+                    vec![
+                        e.name.clone(),
+                        e.current.clone(),
+                        e.available.clone(),
+                        e.repo.clone(),
+                        e.channel.clone(),
+                        e.pinned.clone().unwrap_or_default(),
+                    ]
+                })
+                .collect::<Vec<_>>(),
+        ),
+    }
+
+    Ok(())
+}
+
+fn print_table(db: &InstalledDb, outdated: &[Entry], held: &[Entry]) {
+    if db.tools.is_empty() {
+        println!("No tools are currently installed");
+        return;
+    }
+    if outdated.is_empty() && held.is_empty() {
+        println!("All installed tools are up to date");
+        return;
+    }
+    if outdated.is_empty() {
+        println!("All installed tools are up to date or pinned");
+        print_held(held);
+        return;
+    }
+
+    println!(
+        "{:<20}\t{:<12}\t{:<12}\t{:<8}\tREPO",
+        "NAME", "CURRENT", "AVAILABLE", "CHANNEL"
+    );
+    println!("{}", "-".repeat(76));
+
+    for e in outdated {
+        println!(
+            "{:<20}\t{:<12}\t{:<12}\t{:<8}\t{}",
+            e.name, e.current, e.available, e.channel, e.repo
+        );
+    }
+
+    println!("\nUse 'gpm upgrade' to upgrade {} tool(s)", outdated.len());
+    print_held(held);
+}
+
+fn print_held(held: &[Entry]) {
+    if held.is_empty() {
+        return;
+    }
+
+    println!("\nHeld back by pins (see 'gpm unpin'):");
+    for e in held {
+        println!(
+            "{:<20}\t{:<12}\t{:<12}\t{:<8}\tpinned to {}",
+            e.name,
+            e.current,
+            e.available,
+            e.channel,
+            e.pinned.as_deref().unwrap_or_default()
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::output::{self, fail, say, Kind, OutputFormat};
+use crate::state::InstalledDb;
+use crate::version::Constraint;
+
+/// One pin, as printed by `gpm pin --output json`
+#[derive(Serialize)]
+struct Entry {
+    name: String,
+    pinned: String,
+    installed: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Document {
+    pins: Vec<Entry>,
+}
+
+/// Pin `name`, `name@version` or `name@constraint`, or list the pins when no tool is given
+pub fn run(config: &Config, spec: Option<&str>) -> Result<()> {
+    let mut db = InstalledDb::load(config)?;
+
+    let Some(spec) = spec else {
+        list(&db);
+        return Ok(());
+    };
+
+    let (name, version) = match spec.split_once('@') {
+        Some((name, version)) if !name.is_empty() && !version.trim().is_empty() => {
+            // Checked now rather than at the next upgrade
+            Constraint::parse(version)?;
+            (name, version.trim().to_string())
+        }
+        Some(_) => fail!(
+            Kind::Usage,
+            "Invalid pin '{}', expected <tool>@<version> or <tool>@<constraint>",
+            spec
+        ),
+        // Without a version the tool stays at the one installed now
+        None => match db.get(spec) {
+            Some(installed) => (spec, installed.version.clone()),
+            None => fail!(
+                Kind::NotFound,
+                "Tool '{}' is not installed; pin a version with 'gpm pin {}@<version>'",
+                spec,
+                spec
+            ),
+        },
+    };
+
+    let previous = db.pins.insert(name.to_string(), version.clone());
+    db.save(config)?;
+
+    match previous {
+        Some(old) if old != version => say!("Pinned {} to {} (was {})", name, version, old),
+        _ => say!("Pinned {} to {}", name, version),
+    }
+    let constraint = Constraint::parse(&version)?;
+    if let Some(installed) = db.get(name).filter(|t| !constraint.matches(&t.version)) {
+        say!(
+            "  v{} is installed; 'gpm upgrade {}' moves it into {} once a repository offers it",
+            installed.version,
+            name,
+            version
+        );
+    }
+
+    Ok(())
+}
+
+pub fn unpin(config: &Config, name: &str) -> Result<()> {
+    let mut db = InstalledDb::load(config)?;
+
+    let Some(version) = db.pins.remove(name) else {
+        fail!(Kind::NotFound, "Tool '{}' is not pinned", name);
+    };
+    db.save(config)?;
+
+    say!("Unpinned {} (was held to {})", name, version);
+    Ok(())
+}
+
+fn list(db: &InstalledDb) {
+    let pins: Vec<Entry> = db
+        .pins
+        .iter()
+        .map(|(name, pinned)| Entry {
+            name: name.clone(),
+            pinned: pinned.clone(),
+            installed: db.get(name).map(|t| t.version.clone()),
+        })
+        .collect();
+
+    match output::format() {
+        OutputFormat::Text => {
+            if pins.is_empty() {
+                println!("No tools are pinned");
+                return;
+            }
+
+            println!("{:<20}\t{:<12}\tINSTALLED", "NAME", "PINNED");
+            println!("{}", "-".repeat(50));
+            for pin in &pins {
+                println!(
+                    "{:<20}\t{:<12}\t{}",
+                    pin.name,
+                    pin.pinned,
+                    pin.installed.as_deref().unwrap_or("-")
+                );
+            }
+        }
+        OutputFormat::Json => output::emit_json(&Document { pins }),
+        OutputFormat::Tsv => output::emit_tsv(
+            &["name", "pinned", "installed"],
+            &pins
+                .into_iter()
+                .map(|p| vec![p.name, p.pinned, p.installed.unwrap_or_default()])
+                .collect::<Vec<_>>(),
+        ),
+    }
+}
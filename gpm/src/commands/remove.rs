@@ -1,14 +1,47 @@
 use anyhow::Result;
+use serde::Serialize;
 use std::fs;
 
+use crate::archive;
 use crate::config::Config;
+use crate::output::{self, fail, say, Kind, OutputFormat};
+use crate::state::InstalledDb;
+use crate::versions;
 
-pub fn run(config: &Config, tool_patterns: &[String], all: bool) -> Result<()> {
-    // Get list of installed tools
-    let installed_tools = get_installed_tools(config)?;
+/// Outcome for one tool of a remove run
+#[derive(Serialize)]
+struct ToolResult {
+    name: String,
+    /// `removed` or `failed`
+    status: &'static str,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Document {
+    results: Vec<ToolResult>,
+    summary: Counts,
+}
+
+#[derive(Serialize)]
+struct Counts {
+    removed: usize,
+    failed: usize,
+}
+
+pub fn run(config: &Config, tool_patterns: &[String], all: bool, force: bool) -> Result<()> {
+    // Get list of installed tools; files gpm did not install only count when forced
+    let mut db = InstalledDb::load(config)?;
+    let untracked = db.untracked(config)?;
+    let mut installed_tools: Vec<String> = db.tools.keys().cloned().collect();
+    if force {
+        installed_tools.extend(untracked.iter().cloned());
+        installed_tools.sort();
+    }
 
-    if installed_tools.is_empty() {
-        println!("No tools are currently installed");
+    if installed_tools.is_empty() && untracked.is_empty() {
+        say!("No tools are currently installed");
+        emit(Vec::new(), 0, 0);
         return Ok(());
     }
 
@@ -25,98 +58,146 @@ pub fn run(config: &Config, tool_patterns: &[String], all: bool) -> Result<()> {
     };
 
     if patterns.is_empty() {
-        anyhow::bail!("No valid tool patterns specified. Use --all to remove all tools.");
+        fail!(
+            Kind::Usage,
+            "No valid tool patterns specified. Use --all to remove all tools."
+        );
     }
 
     // Expand patterns to actual tool names
     let mut tools_to_remove = Vec::new();
 
     for pattern in &patterns {
-        if pattern == "*" {
-            // Remove all installed tools
-            for tool_name in &installed_tools {
-                tools_to_remove.push(tool_name.clone());
-            }
-        } else if pattern.contains('*') {
+        if pattern.contains('*') {
             // Wildcard matching against installed tools
-            for tool_name in &installed_tools {
-                if wildcard_match(pattern, tool_name) {
-                    tools_to_remove.push(tool_name.clone());
-                }
-            }
+            tools_to_remove.extend(matching(pattern, &installed_tools));
         } else {
             // Exact tool name
             tools_to_remove.push(pattern.clone());
         }
     }
 
+    // Mention what a wildcard would have removed had it been installed by gpm
+    if !force {
+        let mut spared: Vec<String> = patterns
+            .iter()
+            .filter(|p| p.contains('*'))
+            .flat_map(|p| matching(p, &untracked))
+            .collect();
+        spared.sort();
+        spared.dedup();
+        if !spared.is_empty() {
+            say!(
+                "Leaving {} untracked file(s) alone: {} (use --force to remove them)",
+                spared.len(),
+                spared.join(", ")
+            );
+        }
+    }
+
     if tools_to_remove.is_empty() {
-        anyhow::bail!("No installed tools found matching the given patterns");
+        fail!(
+            Kind::Usage,
+            "No installed tools found matching the given patterns"
+        );
     }
 
     // Remove duplicates
     tools_to_remove.sort();
     tools_to_remove.dedup();
 
-    println!("Removing {} tool(s)...", tools_to_remove.len());
+    // Dependencies can be removed, but not without saying what still needs them
+    for tool_name in &tools_to_remove {
+        let dependents: Vec<&str> = db
+            .dependents(tool_name)
+            .into_iter()
+            .filter(|name| !tools_to_remove.iter().any(|t| t == name))
+            .collect();
+        if !dependents.is_empty() {
+            eprintln!(
+                "Warning: {} is a dependency of {}, which will stay installed",
+                tool_name,
+                dependents.join(", ")
+            );
+        }
+    }
+
+    say!("Removing {} tool(s)...", tools_to_remove.len());
 
     let mut removed_count = 0;
     let mut failed_tools = Vec::new();
+    let mut results = Vec::new();
 
     for tool_name in &tools_to_remove {
-        match remove_single_tool(config, tool_name) {
+        match remove_single_tool(config, &mut db, tool_name, force) {
             Ok(()) => {
                 removed_count += 1;
-                println!("  Removed {}", tool_name);
+                say!("  Removed {}", tool_name);
+                results.push(ToolResult {
+                    name: tool_name.clone(),
+                    status: "removed",
+                    error: None,
+                });
             }
             Err(e) => {
                 eprintln!("  Failed to remove {}: {}", tool_name, e);
                 failed_tools.push(tool_name.clone());
+                results.push(ToolResult {
+                    name: tool_name.clone(),
+                    status: "failed",
+                    error: Some(format!("{:#}", e)),
+                });
             }
         }
     }
 
-    println!("\nSummary:");
-    if removed_count > 0 {
-        println!("  Removed: {}", removed_count);
-    }
-    if !failed_tools.is_empty() {
-        println!(
-            "  Failed: {} ({})",
-            failed_tools.len(),
-            failed_tools.join(", ")
-        );
+    if output::is_text() {
+        println!("\nSummary:");
+        if removed_count > 0 {
+            println!("  Removed: {}", removed_count);
+        }
+        if !failed_tools.is_empty() {
+            println!(
+                "  Failed: {} ({})",
+                failed_tools.len(),
+                failed_tools.join(", ")
+            );
+        }
     }
+    emit(results, removed_count, failed_tools.len());
 
     if !failed_tools.is_empty() {
-        anyhow::bail!("Some tools failed to remove");
+        fail!(Kind::Partial, "Some tools failed to remove");
     }
 
     Ok(())
 }
 
-fn get_installed_tools(config: &Config) -> Result<Vec<String>> {
-    let mut tools = Vec::new();
-
-    if !config.install_dir.exists() {
-        return Ok(tools);
-    }
-
-    for entry in fs::read_dir(&config.install_dir)? {
-        let entry = entry?;
-        let path = entry.path();
-
-        if path.is_file() {
-            if let Some(name) = path.file_name() {
-                if let Some(name_str) = name.to_str() {
-                    tools.push(name_str.to_string());
-                }
-            }
-        }
+/// Print the per-tool results for scripts; text mode prints them as it goes
+fn emit(results: Vec<ToolResult>, removed: usize, failed: usize) {
+    match output::format() {
+        OutputFormat::Text => {}
+        OutputFormat::Json => output::emit_json(&Document {
+            results,
+            summary: Counts { removed, failed },
+        }),
+        OutputFormat::Tsv => output::emit_tsv(
+            &["name", "status", "error"],
+            &results
+                .into_iter()
+                .map(|r| vec![r.name, r.status.to_string(), r.error.unwrap_or_default()])
+                .collect::<Vec<_>>(),
+        ),
     }
+}
 
-    tools.sort();
-    Ok(tools)
+/// Names in `names` that `pattern` selects
+fn matching(pattern: &str, names: &[String]) -> Vec<String> {
+    names
+        .iter()
+        .filter(|name| pattern == "*" || wildcard_match(pattern, name))
+        .cloned()
+        .collect()
 }
 
 fn wildcard_match(pattern: &str, text: &str) -> bool {
@@ -148,13 +229,92 @@ fn wildcard_match(pattern: &str, text: &str) -> bool {
     match_recursive(&pattern_chars, &text_chars, 0, 0)
 }
 
-fn remove_single_tool(config: &Config, tool_name: &str) -> Result<()> {
+fn remove_single_tool(
+    config: &Config,
+    db: &mut InstalledDb,
+    tool_name: &str,
+    force: bool,
+) -> Result<()> {
     let path = config.install_dir.join(tool_name);
+    let record = db.get(tool_name).cloned();
+
+    if record.is_none() {
+        if let Some((owner, _)) = db.tools.iter().find(|(_, t)| t.files.contains(&path)) {
+            fail!(
+                Kind::Usage,
+                "'{}' belongs to package '{}'; remove that instead",
+                tool_name,
+                owner
+            );
+        }
+        if fs::symlink_metadata(&path).is_err() {
+            fail!(Kind::NotFound, "Tool '{}' is not installed", tool_name);
+        }
+        if !force {
+            fail!(
+                Kind::Usage,
+                "'{}' was not installed by gpm; use --force to remove it anyway",
+                tool_name
+            );
+        }
+    }
+
+    match &record {
+        // Packages own their links and prefix directory rather than a single binary
+        Some(installed) if installed.archive.is_some() => archive::remove_files(&installed.files)?,
+        _ => {
+            if fs::symlink_metadata(&path).is_ok() {
+                fs::remove_file(&path)?;
+            }
+        }
+    }
 
-    if !path.exists() {
-        anyhow::bail!("Tool '{}' is not installed", tool_name);
+    if db.forget(tool_name).is_some() {
+        versions::forget(db, tool_name);
+        db.save(config)?;
     }
 
-    fs::remove_file(&path)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::InstalledTool;
+
+    #[test]
+    fn removing_a_tool_keeps_its_artifacts_for_a_reinstall() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(dir.path());
+        fs::create_dir_all(config.artifacts_dir()).unwrap();
+        let record = |version: &str, sha256: &str| InstalledTool {
+            version: version.to_string(),
+            sha256: sha256.to_string(),
+            url: String::new(),
+            installed_at: 0,
+            repo: "main".to_string(),
+            repo_version: "1.0.0".to_string(),
+            size: None,
+            archive: None,
+            files: Vec::new(),
+            channel: None,
+            depends: Vec::new(),
+        };
+        for sha256 in ["new", "old"] {
+            fs::write(config.artifact_path(sha256), sha256).unwrap();
+        }
+        fs::write(config.install_dir.join("jq"), "new").unwrap();
+        let mut db = InstalledDb::default();
+        db.record("jq", record("1.7", "new"));
+        db.history
+            .insert("jq".to_string(), vec![record("1.6", "old")]);
+
+        remove_single_tool(&config, &mut db, "jq", false).unwrap();
+
+        assert!(!config.install_dir.join("jq").exists());
+        assert!(db.get("jq").is_none());
+        assert!(!db.history.contains_key("jq"));
+        assert!(config.artifact_path("new").exists());
+        assert!(config.artifact_path("old").exists());
+    }
+}
@@ -0,0 +1,255 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::cli::RepoAction;
+use crate::config::{self, RepoSettings, Settings, DEFAULT_REPO, LOCK_FILE};
+use crate::lock::{self, LockMode};
+use crate::manifest::STABLE_CHANNEL;
+use crate::output::{self, fail, say, Kind, OutputFormat};
+use crate::signature;
+use crate::transport;
+
+use super::config::target_path;
+
+/// One repository, as printed by `gpm repo list --output json`
+#[derive(Serialize)]
+struct Entry {
+    name: String,
+    url: String,
+    priority: i64,
+    /// Public key pinned for this repository; the default keys apply when unset
+    key: Option<String>,
+    mirrors: Vec<String>,
+    /// Release channel the repository's tools follow
+    channel: String,
+}
+
+#[derive(Serialize)]
+struct Document {
+    repos: Vec<Entry>,
+}
+
+pub fn run(action: &RepoAction, cli_settings: Settings) -> Result<()> {
+    match action {
+        RepoAction::Add {
+            name,
+            url,
+            priority,
+            key,
+            mirrors,
+            channel,
+            system,
+        } => add(
+            name,
+            url,
+            *priority,
+            key.as_deref(),
+            mirrors,
+            channel.as_deref(),
+            *system,
+        ),
+        RepoAction::Remove { name, system } => remove(name, *system, cli_settings),
+        RepoAction::List => list(cli_settings),
+    }
+}
+
+fn add(
+    name: &str,
+    url: &str,
+    priority: i64,
+    key: Option<&str>,
+    mirrors: &[String],
+    channel: Option<&str>,
+    system: bool,
+) -> Result<()> {
+    config::validate_repo_name(name)?;
+    if let Some(channel) = channel {
+        config::validate_channel_name(channel)?;
+    }
+    if url.is_empty() {
+        anyhow::bail!("Repository URL must not be empty");
+    }
+    if let Some(key) = key {
+        signature::parse_key(key)?;
+    }
+    // A key file is stored by absolute path, like local locations below
+    let key = key
+        .map(|key| {
+            if !std::path::Path::new(key).exists() {
+                return Ok(key.to_string());
+            }
+            std::path::absolute(key)
+                .map(|path| path.to_string_lossy().to_string())
+                .context(format!("Invalid key path: {}", key))
+        })
+        .transpose()?;
+    let url = absolute_location(url)?;
+    let mirrors = mirrors
+        .iter()
+        .map(|mirror| absolute_location(mirror))
+        .collect::<Result<Vec<_>>>()?;
+
+    let pinned = key.is_some();
+    let path = target_path(system)?;
+    let mut settings = Settings::load(&path)?;
+    let replaced = settings
+        .repos
+        .insert(
+            name.to_string(),
+            RepoSettings {
+                url,
+                priority,
+                key,
+                mirrors,
+                channel: channel.map(str::to_string),
+            },
+        )
+        .is_some();
+    settings.save(&path)?;
+
+    let action = if replaced { "Updated" } else { "Added" };
+    say!("{} repository {} in {:?}", action, name, path);
+    if !pinned {
+        say!("  Its manifest must be signed by one of the default trusted keys (see --key)");
+    }
+
+    Ok(())
+}
+
+/// Local paths are stored absolute so they do not depend on where gpm runs from
+fn absolute_location(location: &str) -> Result<String> {
+    if location.contains("://") {
+        transport::to_url(location)?;
+        return Ok(location.to_string());
+    }
+
+    let path = std::path::absolute(location).context(format!("Invalid path: {}", location))?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+fn remove(name: &str, system: bool, cli_settings: Settings) -> Result<()> {
+    let path = target_path(system)?;
+    let mut settings = Settings::load(&path)?;
+
+    if settings.repos.remove(name).is_none() {
+        if name == DEFAULT_REPO {
+            anyhow::bail!(
+                "'{}' is the built-in repository; disable it with 'gpm config set manifest_url \"\"'",
+                name
+            );
+        }
+        fail!(
+            Kind::NotFound,
+            "Repository '{}' is not defined in {:?}",
+            name,
+            path
+        );
+    }
+    settings.save(&path)?;
+    say!("Removed repository {} from {:?}", name, path);
+
+    // Drop its cached manifest unless another layer still defines it
+    let effective = Settings::layered(cli_settings)?.with_defaults();
+    if !effective.repos().iter().any(|repo| repo.name == name) {
+        let cache_dir = PathBuf::from(effective.cache_dir.unwrap_or_default());
+        let cache = cache_dir.join("repos").join(name);
+        if cache.exists() {
+            // A running install or update may be reading it
+            let _lock = lock::acquire_at(
+                &cache_dir.join(LOCK_FILE),
+                LockMode::Exclusive,
+                effective.wait.unwrap_or(false),
+            )?;
+            fs::remove_dir_all(&cache).context(format!("Failed to remove {:?}", cache))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn list(cli_settings: Settings) -> Result<()> {
+    let repos = Settings::layered(cli_settings)?.with_defaults().repos();
+
+    match output::format() {
+        OutputFormat::Text => {}
+        OutputFormat::Json => {
+            output::emit_json(&Document {
+                repos: repos
+                    .into_iter()
+                    .map(|repo| Entry {
+                        name: repo.name,
+                        url: repo.url,
+                        priority: repo.priority,
+                        key: repo.key,
+                        mirrors: repo.mirrors,
+                        channel: channel_name(&repo.channel).to_string(),
+                    })
+                    .collect(),
+            });
+            return Ok(());
+        }
+        OutputFormat::Tsv => {
+            output::emit_tsv(
+                &["name", "priority", "url", "key", "mirrors", "channel"],
+                &repos
+                    .into_iter()
+                    .map(|repo| {
+                        vec![
+                            repo.name,
+                            repo.priority.to_string(),
+                            repo.url,
+                            repo.key.unwrap_or_default(),
+                            repo.mirrors.join(" "),
+                            channel_name(&repo.channel).to_string(),
+                        ]
+                    })
+                    .collect::<Vec<_>>(),
+            );
+            return Ok(());
+        }
+    }
+
+    if repos.is_empty() {
+        println!("No repositories configured");
+        return Ok(());
+    }
+
+    println!(
+        "{:<15}\t{:<8}\t{:<12}\t{:<8}\tURL",
+        "NAME", "PRIORITY", "TRUST", "CHANNEL"
+    );
+    println!("{}", "-".repeat(90));
+
+    for repo in &repos {
+        let trust = if repo.key.is_some() {
+            "repo key"
+        } else {
+            "default keys"
+        };
+        println!(
+            "{:<15}\t{:<8}\t{:<12}\t{:<8}\t{}",
+            repo.name,
+            repo.priority,
+            trust,
+            channel_name(&repo.channel),
+            repo.url
+        );
+        for mirror in &repo.mirrors {
+            println!(
+                "{:<15}\t{:<8}\t{:<12}\t{:<8}\tmirror: {}",
+                "", "", "", "", mirror
+            );
+        }
+    }
+
+    println!("\nA tool found in several repositories comes from the first one listed,");
+    println!("unless it is already installed from another");
+
+    Ok(())
+}
+
+fn channel_name(channel: &Option<String>) -> &str {
+    channel.as_deref().unwrap_or(STABLE_CHANNEL)
+}
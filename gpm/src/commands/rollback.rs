@@ -0,0 +1,76 @@
+use anyhow::{Context, Result};
+use std::fs;
+
+use crate::archive;
+use crate::config::Config;
+use crate::downloader;
+use crate::output::{say, Failure, Kind};
+use crate::state::{self, InstalledDb};
+use crate::versions;
+
+pub fn run(config: &Config, tool_name: &str) -> Result<()> {
+    let mut db = InstalledDb::load(config)?;
+
+    let current = db.get(tool_name).cloned().ok_or_else(|| {
+        Failure::new(
+            Kind::NotFound,
+            format!("Tool '{}' is not installed", tool_name),
+        )
+    })?;
+
+    let mut previous = db
+        .history
+        .get_mut(tool_name)
+        .and_then(|history| history.pop())
+        .ok_or_else(|| {
+            Failure::new(
+                Kind::NotFound,
+                format!("No previous version of '{}' to roll back to", tool_name),
+            )
+        })?;
+
+    let stored = versions::stored_path(config, &previous.sha256);
+    downloader::verify_sha256(&stored, &previous.sha256).context(format!(
+        "Archived copy of {} v{} is damaged",
+        tool_name, previous.version
+    ))?;
+
+    let dest = config.install_dir.join(tool_name);
+
+    match previous.archive.clone() {
+        Some(layout) => {
+            // Archive the outgoing version while its files are still the ones installed
+            versions::archive_current(config, &mut db, tool_name, &previous.sha256)?;
+            previous.files = archive::install_package(
+                config,
+                tool_name,
+                &stored,
+                &layout,
+                Some(&current),
+                false,
+            )?;
+        }
+        None => {
+            let staged = versions::staging_path(&dest);
+            fs::copy(&stored, &staged).context("Failed to stage previous version")?;
+
+            // The version being replaced becomes the rollback target in turn
+            versions::archive_current(config, &mut db, tool_name, &previous.sha256)?;
+            versions::swap_into_place(&staged, &dest)?;
+            archive::remove_files_except(&current.files, std::slice::from_ref(&dest))?;
+        }
+    }
+
+    say!(
+        "Rolled back {} from v{} to v{}",
+        tool_name,
+        current.version,
+        previous.version
+    );
+
+    previous.installed_at = state::now_unix();
+    db.record(tool_name, previous);
+    db.save(config)?;
+
+    Ok(())
+}
@@ -0,0 +1,321 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::manifest::{self, Tool};
+use crate::output::{self, fail, Kind, OutputFormat};
+use crate::state::InstalledDb;
+
+/// One search result, as printed by `gpm search --output json`
+#[derive(Serialize)]
+struct Match {
+    name: String,
+    version: String,
+    repo: String,
+    license: String,
+    description: String,
+    /// Higher is more relevant
+    score: u32,
+    /// Whether the repository has a build for this host
+    available: bool,
+    installed_version: Option<String>,
+    installed_repo: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Document {
+    query: String,
+    results: Vec<Match>,
+}
+
+pub fn run(config: &Config, query: &[String]) -> Result<()> {
+    let catalog = manifest::fetch_catalog(config)?;
+    let db = InstalledDb::load(config)?;
+
+    let terms: Vec<String> = query
+        .iter()
+        .flat_map(|q| q.split_whitespace())
+        .map(str::to_lowercase)
+        .collect();
+    if terms.is_empty() {
+        fail!(Kind::Usage, "Nothing to search for");
+    }
+
+    let mut matches: Vec<_> = catalog
+        .repos
+        .iter()
+        .enumerate()
+        .flat_map(|(rank, r)| {
+            r.manifest
+                .tools
+                .iter()
+                .map(move |(name, tool)| (name, rank, r, tool))
+        })
+        .filter_map(|(name, rank, r, tool)| {
+            score(&terms, name, tool).map(|score| (score, name, rank, r, tool))
+        })
+        .collect();
+
+    // Best match first; equal scores by name, then repository preference
+    matches.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)).then(a.2.cmp(&b.2)));
+
+    let results: Vec<Match> = matches
+        .into_iter()
+        .map(|(score, name, _, r, tool)| {
+            let installed = db.get(name);
+            Match {
+                name: name.clone(),
+                version: tool.version.clone(),
+                repo: r.repo.name.clone(),
+                license: tool.license.clone(),
+                description: tool.description.clone(),
+                score,
+                available: tool.artifact_for(&config.targets).is_some(),
+                installed_version: installed.map(|t| t.version.clone()),
+                installed_repo: installed.map(|t| t.repo.clone()),
+            }
+        })
+        .collect();
+
+    match output::format() {
+        OutputFormat::Text => print_table(&terms, &results),
+        OutputFormat::Json => output::emit_json(&Document {
+            query: terms.join(" "),
+            results,
+        }),
+        OutputFormat::Tsv => output::emit_tsv(
+            &[
+                "name",
+                "version",
+                "repo",
+                "license",
+                "score",
+                "available",
+                "installed_version",
+                "installed_repo",
+                "description",
+            ],
+            &results
+                .iter()
+                .map(|m| {
+                    vec![
+                        m.name.clone(),
+                        m.version.clone(),
+                        m.repo.clone(),
+                        m.license.clone(),
+                        m.score.to_string(),
+                        m.available.to_string(),
+                        m.installed_version.clone().unwrap_or_default(),
+                        m.installed_repo.clone().unwrap_or_default(),
+                        m.description.clone(),
+                    ]
+                })
+                .collect::<Vec<_>>(),
+        ),
+    }
+
+    Ok(())
+}
+
+fn print_table(terms: &[String], results: &[Match]) {
+    if results.is_empty() {
+        println!("No tools match '{}'", terms.join(" "));
+        return;
+    }
+
+    println!(
+        "{:<15}\t{:<10}\t{:<12}\t{:<15}\tDESCRIPTION",
+        "NAME", "VERSION", "REPO", "LICENSE"
+    );
+    println!("{}", "-".repeat(96));
+
+    for m in results {
+        let mut notes = String::new();
+        match (&m.installed_repo, &m.installed_version) {
+            (Some(repo), _) if *repo != m.repo => {
+                notes.push_str(&format!(" [installed from {}]", repo))
+            }
+            (Some(_), Some(version)) if *version != m.version => {
+                notes.push_str(&format!(" [installed v{}]", version))
+            }
+            (Some(_), _) => notes.push_str(" [installed]"),
+            (None, _) => {}
+        }
+        if !m.available {
+            notes.push_str(" [unavailable for this host]");
+        }
+
+        println!(
+            "{:<15}\t{:<10}\t{:<12}\t{:<15}\t{}{}",
+            m.name, m.version, m.repo, m.license, m.description, notes
+        );
+    }
+
+    println!("\n{} tool(s) found", results.len());
+}
+
+/// Relevance of a tool to every search term, or `None` if any term does not match
+fn score(terms: &[String], name: &str, tool: &Tool) -> Option<u32> {
+    let name = name.to_lowercase();
+    let description = tool.description.to_lowercase();
+    let license = tool.license.to_lowercase();
+
+    terms
+        .iter()
+        .map(|term| term_score(term, &name, &description, &license))
+        .sum()
+}
+
+/// Exact and substring matches on the name rank highest, then the description
+/// and license, then near misses (typos, abbreviations)
+fn term_score(term: &str, name: &str, description: &str, license: &str) -> Option<u32> {
+    let words = || description.split(|c: char| !c.is_alphanumeric());
+
+    if name == term {
+        Some(100)
+    } else if name.starts_with(term) {
+        Some(80)
+    } else if name.contains(term) {
+        Some(60)
+    } else if words().any(|word| word == term) {
+        Some(40)
+    } else if description.contains(term) {
+        Some(30)
+    } else if license.contains(term) {
+        Some(25)
+    } else if is_close(term, name) || is_subsequence(term, name) {
+        Some(20)
+    } else if words().any(|word| is_close(term, word)) {
+        Some(10)
+    } else {
+        None
+    }
+}
+
+/// Within a typo or two, allowing more for longer words
+fn is_close(term: &str, word: &str) -> bool {
+    let allowed = match term.chars().count() {
+        0..=3 => 0,
+        4..=6 => 1,
+        _ => 2,
+    };
+    allowed > 0 && edit_distance(term, word) <= allowed
+}
+
+/// Whether the characters of `term` appear in `name` in order, starting at the
+/// beginning of the name or of one of its words, e.g. `ffch` in `fastfetch`
+///
+/// Terms shorter than three characters would match almost anything this way.
+fn is_subsequence(term: &str, name: &str) -> bool {
+    let mut term = term.chars();
+    let Some(first) = term.next() else {
+        return false;
+    };
+    let rest = term.as_str();
+    if rest.chars().count() < 2 {
+        return false;
+    }
+
+    let name: Vec<char> = name.chars().collect();
+    (0..name.len())
+        .filter(|&i| name[i] == first && (i == 0 || !name[i - 1].is_alphanumeric()))
+        .any(|i| {
+            let mut chars = name[i + 1..].iter();
+            rest.chars().all(|c| chars.any(|&n| n == c))
+        })
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scored(term: &str, name: &str) -> Option<u32> {
+        term_score(
+            term,
+            name,
+            "gnu make, a build tool for json files",
+            "gpl-3.0-or-later",
+        )
+    }
+
+    #[test]
+    fn ranks_name_matches_above_description_license_and_near_misses() {
+        let order = [
+            scored("make", "make"),
+            scored("make", "makefile"),
+            scored("ake", "make"),
+            scored("build", "make"),
+            scored("buil", "make"),
+            scored("gpl", "make"),
+            scored("mske", "make"),
+            scored("jsom", "make"),
+        ];
+        assert!(order.iter().all(Option::is_some), "{:?}", order);
+        for pair in order.windows(2) {
+            assert!(pair[0] > pair[1], "{:?}", order);
+        }
+        assert_eq!(scored("python", "make"), None);
+        assert_eq!(scored("ffch", "fastfetch"), Some(20));
+        assert_eq!(scored("ge", "grep"), None);
+    }
+
+    #[test]
+    fn sums_every_term_and_requires_all_of_them() {
+        let catalog = crate::manifest::Catalog::for_tests(&[(
+            "main",
+            serde_json::json!({"make": {"version": "4.4", "description": "GNU make"}}),
+        )]);
+        let tool = &catalog.repos[0].manifest.tools["make"];
+        let terms = |t: &[&str]| t.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(score(&terms(&["gnu", "make"]), "make", tool), Some(140));
+        assert_eq!(score(&terms(&["gnu", "python"]), "make", tool), None);
+    }
+
+    #[test]
+    fn measures_edit_distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("make", "make"), 0);
+        assert_eq!(edit_distance("mkae", "make"), 2);
+        assert_eq!(edit_distance("mak", "make"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "jq"), 2);
+
+        assert!(is_close("gawkk", "gawk"));
+        assert!(is_close("starshp", "starship"));
+        assert!(!is_close("jqq", "jq"));
+        assert!(!is_close("grep", "less"));
+    }
+
+    #[test]
+    fn matches_subsequences_only_from_the_start_of_a_word() {
+        assert!(is_subsequence("ffch", "fastfetch"));
+        assert!(is_subsequence("stlc", "set_locale"));
+        assert!(is_subsequence("lcl", "set_locale"));
+
+        assert!(!is_subsequence("ge", "grep"));
+        assert!(!is_subsequence("gp", "gzip"));
+        assert!(!is_subsequence("zp", "gzip"));
+        assert!(!is_subsequence("zip", "gzip"));
+        assert!(!is_subsequence("esl", "less"));
+        assert!(!is_subsequence("mka", "make"));
+        assert!(!is_subsequence("", "make"));
+    }
+}
@@ -4,6 +4,7 @@ use std::fs;
 use std::path::PathBuf;
 
 use crate::config::Config;
+use crate::output::say;
 
 pub fn run(config: &Config) -> Result<()> {
     let home_dir = env::var("HOME").context("HOME environment variable not set")?;
@@ -17,9 +18,9 @@ pub fn run(config: &Config) -> Result<()> {
         config.install_dir.join("gpm").to_string_lossy()
     );
 
-    println!("Setting up PATH for gpm binaries...");
-    println!("Install directory: {}", install_dir);
-    println!();
+    say!("Setting up PATH for gpm binaries...");
+    say!("Install directory: {}", install_dir);
+    say!();
 
     let mut modified_files = Vec::new();
     let mut already_configured = Vec::new();
@@ -42,7 +43,7 @@ pub fn run(config: &Config) -> Result<()> {
             fs::read_to_string(&config_file).context(format!("Failed to read {}", file_name))?;
 
         // Check if already configured
-        if content.contains(&install_dir.to_string()) || content.contains(&marker_comment) {
+        if content.contains(&install_dir.to_string()) || content.contains(marker_comment) {
             already_configured.push(file_name.to_string());
             continue;
         }
@@ -64,31 +65,31 @@ pub fn run(config: &Config) -> Result<()> {
 
     // Report results
     if !modified_files.is_empty() {
-        println!("Added PATH configuration and gpm wrapper to:");
+        say!("Added PATH configuration and gpm wrapper to:");
         for file in &modified_files {
-            println!("  - {}", file);
+            say!("  - {}", file);
         }
-        println!();
-        println!("To apply changes, run:");
-        println!("  source ~/.bashrc   # or ~/.zshrc, ~/.profile");
-        println!();
-        println!("The gpm wrapper prevents shell glob expansion issues.");
-        println!("Or start a new shell session.");
+        say!();
+        say!("To apply changes, run:");
+        say!("  source ~/.bashrc   # or ~/.zshrc, ~/.profile");
+        say!();
+        say!("The gpm wrapper prevents shell glob expansion issues.");
+        say!("Or start a new shell session.");
     }
 
     if !already_configured.is_empty() {
-        println!("Already configured in:");
+        say!("Already configured in:");
         for file in &already_configured {
-            println!("  - {}", file);
+            say!("  - {}", file);
         }
     }
 
     if modified_files.is_empty() && already_configured.is_empty() {
-        println!("Warning: No shell configuration files found.");
-        println!();
-        println!("You can manually add to your shell config:");
-        println!("  {}", path_export);
-        println!("  {}", gpm_wrapper);
+        say!("Warning: No shell configuration files found.");
+        say!();
+        say!("You can manually add to your shell config:");
+        say!("  {}", path_export);
+        say!("  {}", gpm_wrapper);
     }
 
     Ok(())
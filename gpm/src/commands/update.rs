@@ -1,27 +1,129 @@
 use anyhow::Result;
-use std::fs;
+use serde::Serialize;
 
 use crate::config::Config;
-use crate::manifest;
+use crate::manifest::{self, Freshness};
+use crate::output::{self, fail, say, Kind, OutputFormat};
+use crate::transport;
+
+/// Outcome for one repository of an update run
+#[derive(Serialize)]
+struct RepoResult {
+    name: String,
+    /// `fetched`, `not_modified`, `cached`, `stale`, `failed` or `skipped` (offline)
+    status: &'static str,
+    repo_version: Option<String>,
+    tools: Option<usize>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Document {
+    repos: Vec<RepoResult>,
+}
 
 pub fn run(config: &Config) -> Result<()> {
-    let cache_path = config.manifest_cache_path();
+    if config.repos.is_empty() {
+        fail!(
+            Kind::Repository,
+            "No repositories configured (see 'gpm repo add')"
+        );
+    }
 
-    // Remove cached manifest
-    if cache_path.exists() {
-        fs::remove_file(&cache_path)?;
-        println!("Removed cached manifest");
+    let mut failed = 0;
+    let mut results = Vec::new();
+
+    for repo in &config.repos {
+        let mut result = RepoResult {
+            name: repo.name.clone(),
+            status: "skipped",
+            repo_version: None,
+            tools: None,
+            error: None,
+        };
+
+        // Keep the cache: offline it is the only copy there is
+        if config.offline && !transport::is_local(&repo.url) {
+            say!("Skipping {} (offline)", repo.name);
+            results.push(result);
+            continue;
+        }
+
+        // The cache is only replaced once a trusted newer copy arrives
+        say!("Checking repository {} for updates...", repo.name);
+        match manifest::refresh_manifest(config, repo) {
+            Ok((manifest, freshness)) => {
+                result.repo_version = Some(manifest.repo_version.clone());
+                result.tools = Some(manifest.tools.len());
+                result.status = match freshness {
+                    Freshness::Fetched => {
+                        say!(
+                            "Repository {} updated successfully (v{}, {} tools available)",
+                            repo.name,
+                            manifest.repo_version,
+                            manifest.tools.len()
+                        );
+                        "fetched"
+                    }
+                    Freshness::NotModified | Freshness::Cached => {
+                        say!(
+                            "Repository {} is up to date (v{}, {} tools available)",
+                            repo.name,
+                            manifest.repo_version,
+                            manifest.tools.len()
+                        );
+                        if matches!(freshness, Freshness::Cached) {
+                            "cached"
+                        } else {
+                            "not_modified"
+                        }
+                    }
+                    Freshness::Stale { error, age } => {
+                        eprintln!(
+                            "Failed to update repository {}: {:#} (keeping the cached manifest from {} ago)",
+                            repo.name,
+                            error,
+                            manifest::describe_age(age)
+                        );
+                        result.error = Some(format!("{:#}", error));
+                        failed += 1;
+                        "stale"
+                    }
+                };
+            }
+            Err(e) => {
+                eprintln!("Failed to update repository {}: {:#}", repo.name, e);
+                result.status = "failed";
+                result.error = Some(format!("{:#}", e));
+                failed += 1;
+            }
+        }
+        results.push(result);
     }
 
-    // Fetch fresh manifest
-    println!("Fetching fresh manifest...");
-    let manifest = manifest::fetch_manifest(config)?;
+    match output::format() {
+        OutputFormat::Text => {}
+        OutputFormat::Json => output::emit_json(&Document { repos: results }),
+        OutputFormat::Tsv => output::emit_tsv(
+            &["name", "status", "repo_version", "tools", "error"],
+            &results
+                .into_iter()
+                .map(|r| {
+                    vec![
+                        r.name,
+                        r.status.to_string(),
+                        r.repo_version.unwrap_or_default(),
+                        r.tools.map(|n| n.to_string()).unwrap_or_default(),
+                        r.error.unwrap_or_default(),
+                    ]
+                })
+                .collect::<Vec<_>>(),
+        ),
+    }
 
-    println!(
-        "Manifest updated successfully (v{}, {} tools available)",
-        manifest.repo_version,
-        manifest.tools.len()
-    );
+    if failed > 0 {
+        fail!(Kind::Partial, "{} repository(s) failed to update", failed);
+    }
 
     Ok(())
 }
@@ -0,0 +1,62 @@
+use anyhow::Result;
+
+use crate::commands::dependencies::Scope;
+use crate::commands::install;
+use crate::config::Config;
+use crate::manifest;
+use crate::output::{fail, say, Kind};
+use crate::state::InstalledDb;
+
+pub fn run(config: &Config, tool_patterns: &[String], all: bool) -> Result<()> {
+    let catalog = manifest::fetch_catalog(config)?;
+    let mut db = InstalledDb::load(config)?;
+
+    if db.tools.is_empty() {
+        say!("No tools are currently installed");
+        install::Summary::default().emit();
+        return Ok(());
+    }
+
+    let installed: Vec<String> = db.tools.keys().cloned().collect();
+    let tool_names = install::expand_patterns(tool_patterns, all, &installed, "upgrade")?;
+
+    // Upgrade never installs anything new
+    let mut not_installed = Vec::new();
+    let tools_to_upgrade: Vec<String> = tool_names
+        .into_iter()
+        .filter(|name| {
+            if db.get(install::tool_name(name)).is_some() {
+                true
+            } else {
+                eprintln!(
+                    "Tool '{}' is not installed (use 'gpm install {}')",
+                    name, name
+                );
+                not_installed.push(name.clone());
+                false
+            }
+        })
+        .collect();
+
+    let mut summary = install::install_tools(
+        config,
+        &catalog,
+        &mut db,
+        &tools_to_upgrade,
+        false,
+        None,
+        Scope::Installed,
+    );
+    for name in &not_installed {
+        summary.fail(name, "not installed".to_string());
+    }
+    summary.failed.sort();
+    summary.results.sort_by(|a, b| a.name.cmp(&b.name));
+    summary.emit();
+
+    if !summary.failed.is_empty() {
+        fail!(Kind::Partial, "Some tools failed to upgrade");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,499 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::archive;
+use crate::commands::install;
+use crate::config::Config;
+use crate::downloader;
+use crate::manifest::{self, Catalog};
+use crate::mirrors;
+use crate::output::{self, fail, say, Kind, OutputFormat};
+use crate::state::{InstalledDb, InstalledTool};
+use crate::versions;
+
+/// Result of checking one tool, from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    /// Every file matches what gpm installed
+    Ok,
+    /// A file in the install dir that gpm did not install
+    Untracked,
+    /// Same size as the installed build but different contents
+    Modified,
+    /// Unreadable, or a different size than the installed build (truncated or partially written)
+    Corrupted,
+    /// The binary, package directory or one of its links is gone
+    Missing,
+}
+
+impl Status {
+    fn name(self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Untracked => "untracked",
+            Status::Modified => "modified",
+            Status::Corrupted => "corrupted",
+            Status::Missing => "missing",
+        }
+    }
+
+    /// Whether `--repair` should restore the tool
+    fn is_damaged(self) -> bool {
+        self > Status::Untracked
+    }
+}
+
+/// One checked tool, as printed by `gpm verify --output json`
+#[derive(Serialize)]
+struct Entry {
+    name: String,
+    status: Status,
+    path: PathBuf,
+    /// What is wrong, or what an untracked file matches
+    detail: Option<String>,
+    /// Whether `--repair` restored the installed build
+    repaired: bool,
+}
+
+#[derive(Serialize)]
+struct Document {
+    results: Vec<Entry>,
+}
+
+pub fn run(config: &Config, tool_patterns: &[String], repair: bool) -> Result<()> {
+    let mut db = InstalledDb::load(config)?;
+    let untracked = db.untracked(config)?;
+
+    let mut candidates: Vec<String> = db.tools.keys().cloned().collect();
+    candidates.extend(untracked.iter().cloned());
+    if candidates.is_empty() {
+        say!("No tools are currently installed");
+        emit(Vec::new());
+        return Ok(());
+    }
+
+    let names = install::expand_patterns(tool_patterns, false, &candidates, "verify")?;
+    for name in &names {
+        if !candidates.contains(name) {
+            fail!(Kind::NotFound, "Tool '{}' is not installed", name);
+        }
+    }
+
+    say!("Verifying {} tool(s)...", names.len());
+
+    // Untracked files are compared with the manifests, which are only loaded if needed
+    let needs_catalog = names.iter().any(|name| match db.get(name) {
+        Some(record) => record.archive.is_none() && recorded_size(config, record).is_none(),
+        None => true,
+    });
+    let catalog = if needs_catalog {
+        manifest::fetch_catalog(config).ok()
+    } else {
+        None
+    };
+
+    let mut results = Vec::new();
+    for name in &names {
+        let mut entry = match db.get(name) {
+            Some(record) => check_tool(config, catalog.as_ref(), name, record),
+            None => check_untracked(config, catalog.as_ref(), name),
+        };
+
+        if repair && entry.status.is_damaged() {
+            match restore(config, &mut db, name) {
+                Ok(()) => {
+                    let record = db.get(name).context("Repaired tool lost its record")?;
+                    let after = check_tool(config, catalog.as_ref(), name, record);
+                    entry.repaired = after.status == Status::Ok;
+                    if entry.repaired {
+                        say!("Repaired {}", name);
+                    } else {
+                        eprintln!(
+                            "Failed to repair {}: still {} after reinstalling",
+                            name,
+                            after.status.name()
+                        );
+                    }
+                }
+                Err(e) => eprintln!("Failed to repair {}: {:#}", name, e),
+            }
+        }
+
+        results.push(entry);
+    }
+
+    let damaged = results
+        .iter()
+        .filter(|e| e.status.is_damaged() && !e.repaired)
+        .count();
+
+    if output::is_text() {
+        print_table(&results, repair, damaged);
+    }
+    emit(results);
+
+    if damaged > 0 {
+        fail!(Kind::Partial, "{} tool(s) failed verification", damaged);
+    }
+
+    Ok(())
+}
+
+fn emit(results: Vec<Entry>) {
+    match output::format() {
+        OutputFormat::Text => {}
+        OutputFormat::Json => output::emit_json(&Document { results }),
+        OutputFormat::Tsv => output::emit_tsv(
+            &["name", "status", "path", "detail", "repaired"],
+            &results
+                .into_iter()
+                .map(|e| {
+                    vec![
+                        e.name,
+                        e.status.name().to_string(),
+                        e.path.display().to_string(),
+                        e.detail.unwrap_or_default(),
+                        e.repaired.to_string(),
+                    ]
+                })
+                .collect::<Vec<_>>(),
+        ),
+    }
+}
+
+fn print_table(results: &[Entry], repair: bool, damaged: usize) {
+    println!("\n{:<20}\t{:<10}\tDETAIL", "NAME", "STATUS");
+    println!("{}", "-".repeat(70));
+
+    for entry in results {
+        let status = if entry.repaired {
+            "repaired"
+        } else {
+            entry.status.name()
+        };
+        println!(
+            "{:<20}\t{:<10}\t{}",
+            entry.name,
+            status,
+            entry.detail.as_deref().unwrap_or_default()
+        );
+    }
+
+    if damaged > 0 && !repair {
+        println!("\nRun 'gpm verify --repair' to reinstall the damaged tools");
+    }
+}
+
+fn check_tool(
+    config: &Config,
+    catalog: Option<&Catalog>,
+    name: &str,
+    record: &InstalledTool,
+) -> Entry {
+    let (path, status, detail) = match &record.archive {
+        Some(layout) => {
+            let prefix = config.package_dir(name);
+            let (status, detail) = check_package(config, &prefix, record, layout);
+            (prefix, status, detail)
+        }
+        None => {
+            let path = config.install_dir.join(name);
+            let expected_size = recorded_size(config, record)
+                .or_else(|| catalog.and_then(|catalog| manifest_size(config, catalog, record)));
+            let (status, detail) = check_file(&path, &record.sha256, expected_size);
+            (path, status, detail)
+        }
+    };
+
+    Entry {
+        name: name.to_string(),
+        status,
+        path,
+        detail,
+        repaired: false,
+    }
+}
+
+/// Size of the installed build as recorded at install time, else of the cached artifact
+fn recorded_size(config: &Config, record: &InstalledTool) -> Option<u64> {
+    record.size.or_else(|| {
+        fs::metadata(config.artifact_path(&record.sha256))
+            .ok()
+            .map(|m| m.len())
+    })
+}
+
+/// Size the manifests give for the installed build, for records from before sizes were kept
+fn manifest_size(config: &Config, catalog: &Catalog, record: &InstalledTool) -> Option<u64> {
+    catalog.repos.iter().find_map(|r| {
+        r.manifest.tools.values().find_map(|tool| {
+            tool.releases().iter().find_map(|release| {
+                let artifact = release.artifact_for(&config.targets)?;
+                (artifact.sha256 == record.sha256 && artifact.size > 0).then_some(artifact.size)
+            })
+        })
+    })
+}
+
+fn check_package(
+    config: &Config,
+    prefix: &Path,
+    record: &InstalledTool,
+    layout: &manifest::ArchiveLayout,
+) -> (Status, Option<String>) {
+    if !prefix.is_dir() {
+        return (
+            Status::Missing,
+            Some("package directory is gone".to_string()),
+        );
+    }
+
+    for link in record.files.iter().filter(|f| *f != prefix) {
+        if fs::symlink_metadata(link).is_err() {
+            return (Status::Missing, Some(format!("{:?} is gone", link)));
+        }
+        if !link.exists() {
+            return (
+                Status::Missing,
+                Some(format!("{:?} is a broken link", link)),
+            );
+        }
+    }
+
+    // Package contents can only be checked against the archive they were unpacked from
+    let archive_path = config.artifact_path(&record.sha256);
+    if downloader::verify_sha256(&archive_path, &record.sha256).is_err() {
+        return (
+            Status::Ok,
+            Some("links present; contents not checked (archive not cached)".to_string()),
+        );
+    }
+    let files = match archive::checksums(&archive_path, layout) {
+        Ok(files) => files,
+        Err(e) => return (Status::Ok, Some(format!("contents not checked: {:#}", e))),
+    };
+
+    let mut problems: Vec<(Status, String)> = files
+        .iter()
+        .filter_map(|(path, sha256, size)| {
+            let (status, detail) = check_file(&prefix.join(path), sha256, Some(*size));
+            (status != Status::Ok).then(|| {
+                let detail = detail.unwrap_or_default();
+                (status, format!("{}: {}", path.display(), detail))
+            })
+        })
+        .collect();
+    // Report the worst problem, and how many more there are
+    problems.sort_by_key(|(status, _)| std::cmp::Reverse(*status));
+
+    match problems.split_first() {
+        None => (Status::Ok, None),
+        Some(((status, detail), [])) => (*status, Some(detail.clone())),
+        Some(((status, detail), rest)) => (
+            *status,
+            Some(format!("{} (and {} more)", detail, rest.len())),
+        ),
+    }
+}
+
+/// Hash `path` and compare it with what was installed there
+fn check_file(path: &Path, sha256: &str, expected_size: Option<u64>) -> (Status, Option<String>) {
+    let size = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(_) => return (Status::Missing, Some("file is gone".to_string())),
+    };
+
+    let computed = match downloader::sha256_file(path) {
+        Ok(computed) => computed,
+        Err(e) => return (Status::Corrupted, Some(format!("unreadable: {}", e))),
+    };
+    if computed == sha256 {
+        return (Status::Ok, None);
+    }
+
+    match expected_size {
+        Some(expected) if expected != size => (
+            Status::Corrupted,
+            Some(format!("{} bytes, expected {}", size, expected)),
+        ),
+        _ if size == 0 => (Status::Corrupted, Some("file is empty".to_string())),
+        _ => (
+            Status::Modified,
+            Some(format!(
+                "sha256 {} does not match the installed build",
+                computed
+            )),
+        ),
+    }
+}
+
+fn check_untracked(config: &Config, catalog: Option<&Catalog>, name: &str) -> Entry {
+    let path = config.install_dir.join(name);
+
+    // Any binary a repository provides for this host, whatever it is called here
+    let computed = downloader::sha256_file(&path).ok();
+    let matching = catalog.zip(computed).and_then(|(catalog, computed)| {
+        catalog.repos.iter().find_map(|r| {
+            r.manifest.tools.iter().find_map(|(tool_name, tool)| {
+                tool.releases().iter().find_map(|release| {
+                    let artifact = release.artifact_for(&config.targets)?;
+                    (artifact.archive.is_none() && artifact.sha256 == computed)
+                        .then(|| format!("{}/{} v{}", r.repo.name, tool_name, release.version))
+                })
+            })
+        })
+    });
+
+    let detail = match matching {
+        Some(tool) => format!("not installed by gpm; matches {}", tool),
+        None => "not installed by gpm".to_string(),
+    };
+
+    Entry {
+        name: name.to_string(),
+        status: Status::Untracked,
+        path,
+        detail: Some(detail),
+        repaired: false,
+    }
+}
+
+/// Reinstall the recorded build of a tool, from the artifact cache if it is still there
+fn restore(config: &Config, db: &mut InstalledDb, name: &str) -> Result<()> {
+    let record = db
+        .get(name)
+        .cloned()
+        .context(format!("Tool '{}' is not installed", name))?;
+
+    let urls = match config.repos.iter().find(|r| r.name == record.repo) {
+        Some(repo) => mirrors::candidates(config, repo, &record.url, &[]),
+        None => vec![record.url.clone()],
+    };
+    let cached = downloader::download_with_retry(config, &urls, &record.sha256, None)
+        .context(format!("Failed to download {} v{}", name, record.version))?;
+
+    match &record.archive {
+        Some(layout) => {
+            let files =
+                archive::install_package(config, name, &cached, layout, Some(&record), false)?;
+            db.record(name, InstalledTool { files, ..record });
+            db.save(config)?;
+        }
+        None => {
+            let dest = config.install_dir.join(name);
+            let staged = versions::staging_path(&dest);
+            if let Err(e) = fs::copy(&cached, &staged) {
+                let _ = fs::remove_file(&staged);
+                return Err(e).context("Failed to stage binary");
+            }
+            versions::swap_into_place(&staged, &dest)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENTS: &[u8] = b"#!/bin/sh\necho hello\n";
+
+    fn record(sha256: &str, size: Option<u64>) -> InstalledTool {
+        InstalledTool {
+            version: "1.0".to_string(),
+            sha256: sha256.to_string(),
+            url: "https://example.com/hello".to_string(),
+            installed_at: 0,
+            repo: "test".to_string(),
+            repo_version: "1.0.0".to_string(),
+            size,
+            archive: None,
+            files: Vec::new(),
+            channel: None,
+            depends: Vec::new(),
+        }
+    }
+
+    /// Install `contents` as `hello`, recorded as the build `CONTENTS`
+    fn installed(contents: &[u8], size: Option<u64>) -> (tempfile::TempDir, Config, InstalledTool) {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(dir.path());
+        let reference = dir.path().join("reference");
+        fs::write(&reference, CONTENTS).unwrap();
+        let sha256 = downloader::sha256_file(&reference).unwrap();
+        fs::write(config.install_dir.join("hello"), contents).unwrap();
+        (dir, config, record(&sha256, size))
+    }
+
+    #[test]
+    fn intact_file_is_ok() {
+        let (_dir, config, record) = installed(CONTENTS, Some(CONTENTS.len() as u64));
+        assert_eq!(
+            check_tool(&config, None, "hello", &record).status,
+            Status::Ok
+        );
+    }
+
+    #[test]
+    fn size_change_is_corruption_without_the_cached_artifact() {
+        let size = Some(CONTENTS.len() as u64);
+        let (_dir, config, record) = installed(&CONTENTS[..10], size);
+        let entry = check_tool(&config, None, "hello", &record);
+        assert_eq!(entry.status, Status::Corrupted);
+        assert_eq!(
+            entry.detail.as_deref(),
+            Some(format!("10 bytes, expected {}", CONTENTS.len()).as_str())
+        );
+
+        let appended = [CONTENTS, b"junk"].concat();
+        let (_dir, config, record) = installed(&appended, size);
+        let entry = check_tool(&config, None, "hello", &record);
+        assert_eq!(entry.status, Status::Corrupted);
+    }
+
+    #[test]
+    fn same_size_edit_is_a_modification() {
+        let mut edited = CONTENTS.to_vec();
+        edited[15] = b'j';
+        let (_dir, config, record) = installed(&edited, Some(CONTENTS.len() as u64));
+        assert_eq!(
+            check_tool(&config, None, "hello", &record).status,
+            Status::Modified
+        );
+    }
+
+    #[test]
+    fn cached_artifact_gives_the_size_of_old_records() {
+        let (_dir, config, record) = installed(&CONTENTS[..10], None);
+        assert_eq!(
+            check_tool(&config, None, "hello", &record).status,
+            Status::Modified
+        );
+
+        fs::create_dir_all(config.artifacts_dir()).unwrap();
+        fs::write(config.artifact_path(&record.sha256), CONTENTS).unwrap();
+        assert_eq!(
+            check_tool(&config, None, "hello", &record).status,
+            Status::Corrupted
+        );
+    }
+
+    #[test]
+    fn missing_and_empty_files() {
+        let (_dir, config, record) = installed(b"", None);
+        assert_eq!(
+            check_tool(&config, None, "hello", &record).status,
+            Status::Corrupted
+        );
+
+        fs::remove_file(config.install_dir.join("hello")).unwrap();
+        assert_eq!(
+            check_tool(&config, None, "hello", &record).status,
+            Status::Missing
+        );
+    }
+}
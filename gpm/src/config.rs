@@ -1,39 +1,767 @@
 use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::output::{fail, Failure, Kind};
+use crate::platform;
+use crate::transport;
+
+pub const DEFAULT_INSTALL_DIR: &str = "/DATA/AppData/glochidia/bin";
+pub const DEFAULT_CACHE_DIR: &str = "/DATA/AppData/glochidia/.cache";
+pub const DEFAULT_MANIFEST_URL: &str =
+    "https://github.com/uairhahs/glochidia/releases/download/latest/manifest.json";
+const DEFAULT_TIMEOUT_SECS: u64 = 300;
+const DEFAULT_JOBS: usize = 4;
+const DEFAULT_KEEP_VERSIONS: usize = 2;
+const DEFAULT_CACHE_TTL_SECS: u64 = 24 * 3600;
+
+/// Advisory lock file in the cache dir, see [`crate::lock`]
+pub const LOCK_FILE: &str = "gpm.lock";
+
+/// Name of the repository described by the `manifest_url` setting
+pub const DEFAULT_REPO: &str = "glochidia";
+
+/// System-wide settings, kept with the rest of gpm's data on the writable partition
+pub const SYSTEM_CONFIG_PATH: &str = "/DATA/AppData/glochidia/gpm.toml";
+
+/// Settings as read from one layer (a gpm.toml file, the environment or the command line)
+///
+/// Unset fields fall through to the layer below.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub install_dir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_dir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manifest_url: Option<String>,
+    /// HTTP(S) proxy for all requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// Per-request timeout in seconds, 0 for none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<usize>,
+    /// Refuse unsigned or badly signed manifests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub require_signature: Option<bool>,
+    /// Previous versions kept per tool for rollback
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_versions: Option<usize>,
+    /// Wait for a running gpm instead of failing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wait: Option<bool>,
+    /// Seconds a cached manifest is used before it is revalidated, 0 for every run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_ttl: Option<u64>,
+    /// Use only cached manifests and artifacts and local repositories
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offline: Option<bool>,
+    /// Mirror base URLs for the built-in repository, tried before its own URLs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mirrors: Option<Vec<String>>,
+    /// How to order the URLs a file can be fetched from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mirror_strategy: Option<MirrorStrategy>,
+    /// Release channel for the built-in repository
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    /// Additional repositories by name, managed with `gpm repo`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub repos: BTreeMap<String, RepoSettings>,
+}
+
+/// A `[repos.<name>]` table in gpm.toml
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepoSettings {
+    /// Manifest URL
+    pub url: String,
+    /// Repositories with a higher priority win when several provide a tool
+    #[serde(default)]
+    pub priority: i64,
+    /// Minisign public key (base64, or a path to a .pub file) that must sign this manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Mirror base URLs, tried before the URLs in the manifest
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+    /// Release channel tools from this repository follow, `stable` when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+}
+
+/// A configured repository
+#[derive(Debug, Clone)]
+pub struct Repo {
+    pub name: String,
+    pub url: String,
+    pub priority: i64,
+    pub key: Option<String>,
+    pub mirrors: Vec<String>,
+    pub channel: Option<String>,
+}
+
+/// Order in which the URLs for one file are tried
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MirrorStrategy {
+    /// User mirrors, then the primary URL, then the manifest's mirrors
+    Ordered,
+    /// Fastest responding host first
+    Latency,
+}
+
+impl std::fmt::Display for MirrorStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MirrorStrategy::Ordered => write!(f, "ordered"),
+            MirrorStrategy::Latency => write!(f, "latency"),
+        }
+    }
+}
+
+impl std::str::FromStr for MirrorStrategy {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, ()> {
+        match s {
+            "ordered" => Ok(MirrorStrategy::Ordered),
+            "latency" => Ok(MirrorStrategy::Latency),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Settings {
+    pub const KEYS: &'static [&'static str] = &[
+        "install_dir",
+        "cache_dir",
+        "manifest_url",
+        "proxy",
+        "timeout",
+        "jobs",
+        "require_signature",
+        "keep_versions",
+        "wait",
+        "cache_ttl",
+        "mirrors",
+        "mirror_strategy",
+        "offline",
+        "channel",
+    ];
+
+    /// Read a gpm.toml file; a missing file is an empty layer
+    pub fn load(path: &PathBuf) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Settings::default());
+        }
+
+        let text = fs::read_to_string(path).context(format!("Failed to read {:?}", path))?;
+        toml::from_str(&text).context(format!("Invalid configuration in {:?}", path))
+    }
+
+    pub fn save(&self, path: &PathBuf) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context(format!("Failed to create {:?}", parent))?;
+        }
+
+        let text = toml::to_string_pretty(self)?;
+        fs::write(path, text).context(format!("Failed to write {:?}", path))
+    }
+
+    /// `GPM_<KEY>` environment variables, e.g. `GPM_INSTALL_DIR`
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(|var| env::var(var).ok())
+    }
+
+    fn from_vars(lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let mut settings = Settings::default();
+
+        for key in Self::KEYS {
+            let var = format!("GPM_{}", key.to_uppercase());
+            if let Some(value) = lookup(&var) {
+                settings
+                    .set(key, &value)
+                    .context(format!("Invalid value in {}", var))?;
+            }
+        }
+
+        Ok(settings)
+    }
+
+    /// System file, then user file, then the environment, then `overrides`
+    pub fn layered(overrides: Settings) -> Result<Self> {
+        Self::layered_from(
+            &PathBuf::from(SYSTEM_CONFIG_PATH),
+            user_config_path(),
+            Settings::from_env()?,
+            overrides,
+        )
+    }
+
+    fn layered_from(
+        system_path: &PathBuf,
+        user_path: Option<PathBuf>,
+        env: Settings,
+        overrides: Settings,
+    ) -> Result<Self> {
+        let mut settings = Settings::load(system_path)?;
+
+        if let Some(user_path) = user_path {
+            settings = settings.merge(Settings::load(&user_path)?);
+        }
+
+        Ok(settings.merge(env).merge(overrides))
+    }
+
+    /// Combine two layers, `over` taking precedence
+    pub fn merge(self, over: Settings) -> Settings {
+        let mut repos = self.repos;
+        repos.extend(over.repos);
+
+        Settings {
+            install_dir: over.install_dir.or(self.install_dir),
+            cache_dir: over.cache_dir.or(self.cache_dir),
+            manifest_url: over.manifest_url.or(self.manifest_url),
+            proxy: over.proxy.or(self.proxy),
+            timeout: over.timeout.or(self.timeout),
+            jobs: over.jobs.or(self.jobs),
+            require_signature: over.require_signature.or(self.require_signature),
+            keep_versions: over.keep_versions.or(self.keep_versions),
+            wait: over.wait.or(self.wait),
+            cache_ttl: over.cache_ttl.or(self.cache_ttl),
+            mirrors: over.mirrors.or(self.mirrors),
+            mirror_strategy: over.mirror_strategy.or(self.mirror_strategy),
+            offline: over.offline.or(self.offline),
+            channel: over.channel.or(self.channel),
+            repos,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        let value = match key {
+            "install_dir" => self.install_dir.clone(),
+            "cache_dir" => self.cache_dir.clone(),
+            "manifest_url" => self.manifest_url.clone(),
+            "proxy" => self.proxy.clone(),
+            "timeout" => self.timeout.map(|v| v.to_string()),
+            "jobs" => self.jobs.map(|v| v.to_string()),
+            "require_signature" => self.require_signature.map(|v| v.to_string()),
+            "keep_versions" => self.keep_versions.map(|v| v.to_string()),
+            "wait" => self.wait.map(|v| v.to_string()),
+            "cache_ttl" => self.cache_ttl.map(|v| v.to_string()),
+            "mirrors" => self.mirrors.as_ref().map(|v| v.join(",")),
+            "mirror_strategy" => self.mirror_strategy.map(|v| v.to_string()),
+            "offline" => self.offline.map(|v| v.to_string()),
+            "channel" => self.channel.clone(),
+            _ => fail!(Kind::Usage, "Unknown setting '{}'", key),
+        };
+
+        Ok(value)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "install_dir" => self.install_dir = Some(value.to_string()),
+            "cache_dir" => self.cache_dir = Some(value.to_string()),
+            "manifest_url" => self.manifest_url = Some(value.to_string()),
+            "proxy" => self.proxy = Some(value.to_string()),
+            "timeout" => self.timeout = Some(parse(key, value)?),
+            "jobs" => self.jobs = Some(parse(key, value)?),
+            "require_signature" => self.require_signature = Some(parse_bool(key, value)?),
+            "keep_versions" => self.keep_versions = Some(parse(key, value)?),
+            "wait" => self.wait = Some(parse_bool(key, value)?),
+            "cache_ttl" => self.cache_ttl = Some(parse(key, value)?),
+            "mirrors" => {
+                self.mirrors = Some(
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|url| !url.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                )
+            }
+            "mirror_strategy" => self.mirror_strategy = Some(parse(key, value)?),
+            "offline" => self.offline = Some(parse_bool(key, value)?),
+            "channel" => {
+                validate_channel_name(value)?;
+                self.channel = Some(value.to_string())
+            }
+            _ => fail!(Kind::Usage, "Unknown setting '{}'", key),
+        }
+
+        Ok(())
+    }
+
+    pub fn unset(&mut self, key: &str) -> Result<()> {
+        match key {
+            "install_dir" => self.install_dir = None,
+            "cache_dir" => self.cache_dir = None,
+            "manifest_url" => self.manifest_url = None,
+            "proxy" => self.proxy = None,
+            "timeout" => self.timeout = None,
+            "jobs" => self.jobs = None,
+            "require_signature" => self.require_signature = None,
+            "keep_versions" => self.keep_versions = None,
+            "wait" => self.wait = None,
+            "cache_ttl" => self.cache_ttl = None,
+            "mirrors" => self.mirrors = None,
+            "mirror_strategy" => self.mirror_strategy = None,
+            "offline" => self.offline = None,
+            "channel" => self.channel = None,
+            _ => fail!(Kind::Usage, "Unknown setting '{}'", key),
+        }
+
+        Ok(())
+    }
+
+    /// Fill every unset field with its built-in default
+    pub fn with_defaults(self) -> Settings {
+        Settings {
+            install_dir: self.install_dir.or(Some(DEFAULT_INSTALL_DIR.to_string())),
+            cache_dir: self.cache_dir.or(Some(DEFAULT_CACHE_DIR.to_string())),
+            manifest_url: self.manifest_url.or(Some(DEFAULT_MANIFEST_URL.to_string())),
+            proxy: self.proxy,
+            timeout: self.timeout.or(Some(DEFAULT_TIMEOUT_SECS)),
+            jobs: self.jobs.or(Some(DEFAULT_JOBS)),
+            require_signature: self.require_signature.or(Some(true)),
+            keep_versions: self.keep_versions.or(Some(DEFAULT_KEEP_VERSIONS)),
+            wait: self.wait.or(Some(false)),
+            cache_ttl: self.cache_ttl.or(Some(DEFAULT_CACHE_TTL_SECS)),
+            mirrors: self.mirrors.or(Some(Vec::new())),
+            mirror_strategy: self.mirror_strategy.or(Some(MirrorStrategy::Ordered)),
+            offline: self.offline.or(Some(false)),
+            channel: self.channel,
+            repos: self.repos,
+        }
+    }
+
+    /// Every configured repository, most preferred first
+    ///
+    /// `manifest_url` is the `glochidia` repository unless a `[repos.glochidia]`
+    /// table replaces it; an empty `manifest_url` disables it. Ties in priority
+    /// are broken by name.
+    pub fn repos(&self) -> Vec<Repo> {
+        let mut repos: Vec<Repo> = self
+            .repos
+            .iter()
+            .map(|(name, repo)| Repo {
+                name: name.clone(),
+                url: repo.url.clone(),
+                priority: repo.priority,
+                key: repo.key.clone(),
+                mirrors: repo.mirrors.clone(),
+                channel: repo.channel.clone(),
+            })
+            .collect();
+
+        if let Some(url) = self.manifest_url.as_ref().filter(|url| !url.is_empty()) {
+            if !self.repos.contains_key(DEFAULT_REPO) {
+                repos.push(Repo {
+                    name: DEFAULT_REPO.to_string(),
+                    url: url.clone(),
+                    priority: 0,
+                    key: None,
+                    mirrors: self.mirrors.clone().unwrap_or_default(),
+                    channel: self.channel.clone(),
+                });
+            }
+        }
+
+        repos.sort_by(|a, b| {
+            b.priority
+                .cmp(&a.priority)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        repos
+    }
+}
+
+/// Channel names appear in manifests, gpm.toml and the installed-state database
+pub fn validate_channel_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+
+    if !valid {
+        fail!(
+            Kind::Usage,
+            "Invalid channel name '{}' (use lowercase letters, digits and '-')",
+            name
+        );
+    }
+
+    Ok(())
+}
+
+/// Repository names double as cache directory names and `repo/tool` prefixes
+pub fn validate_repo_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        && !name.starts_with('.');
+
+    if !valid {
+        fail!(
+            Kind::Usage,
+            "Invalid repository name '{}' (use letters, digits, '-', '_' and '.')",
+            name
+        );
+    }
+
+    Ok(())
+}
+
+fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T> {
+    value.parse().map_err(|_| {
+        Failure::new(
+            Kind::Usage,
+            format!("Invalid value '{}' for {}", value, key),
+        )
+        .into()
+    })
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool> {
+    match value.to_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" => Ok(false),
+        _ => fail!(
+            Kind::Usage,
+            "Invalid value '{}' for {} (expected true or false)",
+            value,
+            key
+        ),
+    }
+}
+
+/// `$GPM_CONFIG`, else `$XDG_CONFIG_HOME/gpm/gpm.toml`, else `~/.config/gpm/gpm.toml`
+pub fn user_config_path() -> Option<PathBuf> {
+    if let Ok(path) = env::var("GPM_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+
+    let config_home = env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .ok()?;
+
+    Some(config_home.join("gpm").join("gpm.toml"))
+}
 
 pub struct Config {
     pub install_dir: PathBuf,
     pub cache_dir: PathBuf,
-    pub manifest_url: String,
+    /// Repositories to resolve tools from, most preferred first
+    pub repos: Vec<Repo>,
     pub verbose: bool,
+    pub insecure: bool,
+    /// Accept expired manifests and ones with a lower serial than already seen
+    pub allow_stale_manifest: bool,
+    /// Maximum number of parallel downloads
+    pub jobs: usize,
+    pub keep_versions: usize,
+    pub wait: bool,
+    /// How long a cached manifest is used without asking the server
+    pub cache_ttl: Duration,
+    pub mirror_strategy: MirrorStrategy,
+    /// Never touch the network
+    pub offline: bool,
+    pub proxy: Option<String>,
+    pub timeout: Option<Duration>,
+    /// Target triples this host can run, most preferred first
+    pub targets: Vec<String>,
+    http: OnceLock<reqwest::blocking::Client>,
 }
 
 impl Config {
-    pub fn new(
-        install_dir: String,
-        cache_dir: String,
-        manifest_url: String,
-        verbose: bool,
-    ) -> Result<Self> {
-        let install_dir = PathBuf::from(install_dir);
-        let cache_dir = PathBuf::from(cache_dir);
+    /// Build the effective configuration from every settings layer
+    pub fn new(overrides: Settings, verbose: bool) -> Result<Self> {
+        let settings = Settings::layered(overrides)?.with_defaults();
+        let mut repos = settings.repos();
+        for repo in &mut repos {
+            validate_repo_name(&repo.name)?;
+            repo.url = transport::manifest_url(&repo.url)
+                .context(format!("Invalid URL for repository '{}'", repo.name))?;
+            for mirror in &mut repo.mirrors {
+                *mirror = transport::to_url(mirror)
+                    .context(format!("Invalid mirror for repository '{}'", repo.name))?;
+            }
+            if let Some(channel) = &repo.channel {
+                validate_channel_name(channel)?;
+            }
+        }
+
+        let install_dir = PathBuf::from(settings.install_dir.unwrap_or_default());
+        let cache_dir = PathBuf::from(settings.cache_dir.unwrap_or_default());
 
         // Create directories if they don't exist
-        fs::create_dir_all(&install_dir)
-            .context(format!("Failed to create install directory: {:?}", install_dir))?;
+        fs::create_dir_all(&install_dir).context(format!(
+            "Failed to create install directory: {:?}",
+            install_dir
+        ))?;
         fs::create_dir_all(&cache_dir)
             .context(format!("Failed to create cache directory: {:?}", cache_dir))?;
 
         Ok(Config {
             install_dir,
             cache_dir,
-            manifest_url,
+            repos,
             verbose,
+            insecure: !settings.require_signature.unwrap_or(true),
+            allow_stale_manifest: false,
+            jobs: settings.jobs.unwrap_or(DEFAULT_JOBS).max(1),
+            keep_versions: settings.keep_versions.unwrap_or(DEFAULT_KEEP_VERSIONS),
+            wait: settings.wait.unwrap_or(false),
+            cache_ttl: Duration::from_secs(settings.cache_ttl.unwrap_or(DEFAULT_CACHE_TTL_SECS)),
+            mirror_strategy: settings.mirror_strategy.unwrap_or(MirrorStrategy::Ordered),
+            offline: settings.offline.unwrap_or(false),
+            proxy: settings.proxy.filter(|p| !p.is_empty()),
+            timeout: settings
+                .timeout
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            targets: platform::host_targets(),
+            http: OnceLock::new(),
+        })
+    }
+
+    /// Shared HTTP client honouring the proxy and timeout settings
+    ///
+    /// Fails in offline mode, so nothing can reach the network by accident.
+    pub fn http(&self) -> Result<&reqwest::blocking::Client> {
+        if self.offline {
+            anyhow::bail!("Network access is disabled in offline mode");
+        }
+        if let Some(client) = self.http.get() {
+            return Ok(client);
+        }
+
+        let mut builder = reqwest::blocking::Client::builder()
+            .user_agent(concat!("gpm/", env!("CARGO_PKG_VERSION")))
+            .timeout(self.timeout);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(
+                reqwest::Proxy::all(proxy).context(format!("Invalid proxy URL: {}", proxy))?,
+            );
+        }
+        let client = builder.build().context("Failed to create HTTP client")?;
+
+        Ok(self.http.get_or_init(|| client))
+    }
+
+    pub fn repo(&self, name: &str) -> Option<&Repo> {
+        self.repos.iter().find(|repo| repo.name == name)
+    }
+
+    /// Per-repository cache of the manifest and its signature
+    pub fn repo_cache_dir(&self, repo_name: &str) -> PathBuf {
+        self.cache_dir.join("repos").join(repo_name)
+    }
+
+    pub fn manifest_cache_path(&self, repo_name: &str) -> PathBuf {
+        self.repo_cache_dir(repo_name).join("manifest.json")
+    }
+
+    pub fn manifest_signature_cache_path(&self, repo_name: &str) -> PathBuf {
+        self.repo_cache_dir(repo_name).join("manifest.json.minisig")
+    }
+
+    /// Highest manifest serial accepted from the repository
+    pub fn manifest_serial_path(&self, repo_name: &str) -> PathBuf {
+        self.repo_cache_dir(repo_name).join("serial")
+    }
+
+    /// Where and when the cached manifest was last fetched or revalidated
+    pub fn manifest_info_cache_path(&self, repo_name: &str) -> PathBuf {
+        self.repo_cache_dir(repo_name).join("manifest.info.json")
+    }
+
+    pub fn trusted_keys_dir(&self) -> PathBuf {
+        self.cache_dir.join("trusted-keys")
+    }
+
+    /// Root of per-package prefixes, a `share` directory next to `install_dir`
+    pub fn share_dir(&self) -> PathBuf {
+        self.install_dir
+            .parent()
+            .unwrap_or(&self.install_dir)
+            .join("share")
+    }
+
+    pub fn package_dir(&self, tool_name: &str) -> PathBuf {
+        self.share_dir().join(tool_name)
+    }
+
+    /// Verified downloads, named by their sha256
+    pub fn artifacts_dir(&self) -> PathBuf {
+        self.cache_dir.join("artifacts")
+    }
+
+    pub fn artifact_path(&self, sha256: &str) -> PathBuf {
+        self.artifacts_dir().join(sha256)
+    }
+
+    /// Interrupted downloads waiting to be resumed
+    pub fn partial_dir(&self) -> PathBuf {
+        self.cache_dir.join("partial")
+    }
+
+    pub fn partial_path(&self, sha256: &str) -> PathBuf {
+        self.partial_dir().join(format!("{}.part", sha256))
+    }
+
+    pub fn lock_path(&self) -> PathBuf {
+        self.cache_dir.join(LOCK_FILE)
+    }
+
+    pub fn state_path(&self) -> PathBuf {
+        self.cache_dir.join("installed.json")
+    }
+}
+
+#[cfg(test)]
+impl Config {
+    /// Defaults rooted at `root`, without reading any settings file or the environment
+    pub fn for_tests(root: &std::path::Path) -> Config {
+        let install_dir = root.join("bin");
+        let cache_dir = root.join(".cache");
+        fs::create_dir_all(&install_dir).unwrap();
+        fs::create_dir_all(&cache_dir).unwrap();
+
+        Config {
+            install_dir,
+            cache_dir,
+            repos: Vec::new(),
+            verbose: false,
+            insecure: false,
+            allow_stale_manifest: false,
+            jobs: 1,
+            keep_versions: DEFAULT_KEEP_VERSIONS,
+            wait: false,
+            cache_ttl: Duration::from_secs(DEFAULT_CACHE_TTL_SECS),
+            mirror_strategy: MirrorStrategy::Ordered,
+            offline: true,
+            proxy: None,
+            timeout: None,
+            targets: vec![platform::DEFAULT_TARGET.to_string()],
+            http: OnceLock::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &PathBuf, text: &str) {
+        fs::write(path, text).unwrap();
+    }
+
+    #[test]
+    fn later_layers_win_and_unset_fields_fall_through() {
+        let dir = tempfile::tempdir().unwrap();
+        let system = dir.path().join("system.toml");
+        let user = dir.path().join("user.toml");
+        write(
+            &system,
+            "jobs = 1\nkeep_versions = 1\ncache_ttl = 10\nwait = true\nproxy = \"http://system\"\n\
+             [repos.shared]\nurl = \"https://system/manifest.json\"\n\
+             [repos.base]\nurl = \"https://base/manifest.json\"\n",
+        );
+        write(
+            &user,
+            "jobs = 2\nkeep_versions = 2\ncache_ttl = 20\n\
+             [repos.shared]\nurl = \"https://user/manifest.json\"\npriority = 5\n",
+        );
+        let env = Settings::from_vars(|var| match var {
+            "GPM_JOBS" => Some("3".to_string()),
+            "GPM_KEEP_VERSIONS" => Some("3".to_string()),
+            _ => None,
         })
+        .unwrap();
+        let cli = Settings {
+            jobs: Some(4),
+            ..Settings::default()
+        };
+
+        let settings = Settings::layered_from(&system, Some(user), env, cli).unwrap();
+
+        assert_eq!(settings.jobs, Some(4));
+        assert_eq!(settings.keep_versions, Some(3));
+        assert_eq!(settings.cache_ttl, Some(20));
+        assert_eq!(settings.wait, Some(true));
+        assert_eq!(settings.proxy.as_deref(), Some("http://system"));
+        assert_eq!(settings.timeout, None);
+
+        // Repositories merge by name, a later table replacing the whole entry
+        let shared = &settings.repos["shared"];
+        assert_eq!(shared.url, "https://user/manifest.json");
+        assert_eq!(shared.priority, 5);
+        assert!(settings.repos.contains_key("base"));
     }
 
-    pub fn manifest_cache_path(&self) -> PathBuf {
-        self.cache_dir.join("manifest.json")
+    #[test]
+    fn missing_files_are_empty_layers() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = Settings::layered_from(
+            &dir.path().join("system.toml"),
+            Some(dir.path().join("user.toml")),
+            Settings::default(),
+            Settings::default(),
+        )
+        .unwrap();
+
+        assert_eq!(settings.jobs, None);
+        assert_eq!(settings.with_defaults().jobs, Some(DEFAULT_JOBS));
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected_naming_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let system = dir.path().join("system.toml");
+        let user = dir.path().join("user.toml");
+        write(&system, "jobs = 2\n");
+        write(&user, "jbos = 3\n");
+
+        let err = Settings::layered_from(
+            &system,
+            Some(user.clone()),
+            Settings::default(),
+            Settings::default(),
+        )
+        .unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.contains(&format!("{:?}", user)), "{}", message);
+        assert!(message.contains("jbos"), "{}", message);
+
+        // Repository tables are checked too
+        write(
+            &user,
+            "[repos.extra]\nurl = \"https://x/manifest.json\"\nprio = 1\n",
+        );
+        let err = Settings::load(&user).unwrap_err();
+        let message = format!("{:#}", err);
+        assert!(message.contains(&format!("{:?}", user)), "{}", message);
+        assert!(message.contains("prio"), "{}", message);
+    }
+
+    #[test]
+    fn invalid_environment_values_name_the_variable() {
+        let err =
+            Settings::from_vars(|var| (var == "GPM_JOBS").then(|| "many".to_string())).unwrap_err();
+
+        assert!(format!("{:#}", err).contains("GPM_JOBS"));
     }
 }
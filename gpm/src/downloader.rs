@@ -1,58 +1,191 @@
 use anyhow::{Context, Result};
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
 use sha2::{Digest, Sha256};
-use std::fs::{self, File};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
 use std::io::{Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 use std::thread;
 use std::time::Duration;
 
+use crate::config::Config;
+use crate::output::say;
+use crate::transport;
+
 const MAX_RETRIES: u32 = 3;
 const RETRY_DELAY_MS: u64 = 1000;
 
-pub fn download_with_retry(url: &str, dest: &Path, expected_sha256: &str) -> Result<()> {
+/// One file for `download_all` to fetch
+pub struct Download {
+    /// Reported back with the error if the download fails
+    pub key: String,
+    pub urls: Vec<String>,
+    pub sha256: String,
+}
+
+/// Fetch several files into the artifact cache, `config.jobs` at a time
+///
+/// Every download must have a distinct sha256 so no two workers write the same
+/// partial file. Returns the failures by key.
+pub fn download_all(config: &Config, downloads: &[Download]) -> HashMap<String, anyhow::Error> {
+    let multi = MultiProgress::new();
+    let next = AtomicUsize::new(0);
+    let failures = Mutex::new(HashMap::new());
+
+    thread::scope(|scope| {
+        for _ in 0..config.jobs.min(downloads.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(download) = downloads.get(index) else {
+                    break;
+                };
+
+                let result =
+                    download_with_retry(config, &download.urls, &download.sha256, Some(&multi));
+                if let Err(e) = result {
+                    failures.lock().unwrap().insert(download.key.clone(), e);
+                }
+            });
+        }
+    });
+
+    failures.into_inner().unwrap()
+}
+
+/// Fetch an artifact into the content-addressed cache and return its path
+///
+/// A cached copy that still verifies is used without touching the network.
+/// Interrupted downloads are resumed from where they stopped. Each attempt
+/// tries every URL in `urls` in turn; whichever serves a file matching
+/// `expected_sha256` wins. Progress bars are added to `multi` when several
+/// downloads run at once.
+pub fn download_with_retry(
+    config: &Config,
+    urls: &[String],
+    expected_sha256: &str,
+    multi: Option<&MultiProgress>,
+) -> Result<PathBuf> {
+    let cached = config.artifact_path(expected_sha256);
+
+    if cached.exists() {
+        if verify_sha256(&cached, expected_sha256).is_ok() {
+            if config.verbose {
+                say!("Using cached artifact {}", expected_sha256);
+            }
+            return Ok(cached);
+        }
+        fs::remove_file(&cached)?;
+    }
+
+    fs::create_dir_all(config.partial_dir()).context("Failed to create download directory")?;
+    fs::create_dir_all(config.artifacts_dir()).context("Failed to create artifact cache")?;
+
+    if urls.is_empty() && config.offline {
+        anyhow::bail!("Artifact is not in the download cache and cannot be fetched offline");
+    }
+
+    let partial = config.partial_path(expected_sha256);
     let mut last_error = None;
 
+    let report = |message: String| match multi {
+        Some(multi) => multi.suspend(|| eprintln!("{}", message)),
+        None => eprintln!("{}", message),
+    };
+
     for attempt in 1..=MAX_RETRIES {
-        match download_file(url, dest, expected_sha256) {
-            Ok(_) => return Ok(()),
-            Err(e) => {
-                last_error = Some(e);
-                if attempt < MAX_RETRIES {
-                    eprintln!(
-                        "Download attempt {} failed, retrying in {}ms...",
-                        attempt, RETRY_DELAY_MS
-                    );
-                    thread::sleep(Duration::from_millis(RETRY_DELAY_MS));
+        for url in urls {
+            match download_file(config, url, &partial, expected_sha256, multi) {
+                Ok(_) => {
+                    fs::rename(&partial, &cached).context("Failed to move download into cache")?;
+                    return Ok(cached);
+                }
+                Err(e) => {
+                    if config.verbose && urls.len() > 1 {
+                        report(format!("Download from {} failed: {:#}", url, e));
+                    }
+                    last_error = Some(e);
                 }
             }
         }
+
+        if attempt < MAX_RETRIES {
+            let name = urls.first().map(String::as_str).unwrap_or_default();
+            report(format!(
+                "Download attempt {} failed for {}, retrying in {}ms...",
+                attempt, name, RETRY_DELAY_MS
+            ));
+            thread::sleep(Duration::from_millis(RETRY_DELAY_MS));
+        }
     }
 
-    Err(last_error.unwrap())
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No download URL available")))
 }
 
-fn download_file(url: &str, dest: &Path, expected_sha256: &str) -> Result<()> {
-    // Download to temporary file
-    let temp_path = dest.with_extension("tmp");
+fn download_file(
+    config: &Config,
+    url: &str,
+    partial: &Path,
+    expected_sha256: &str,
+    multi: Option<&MultiProgress>,
+) -> Result<()> {
+    if let Some(path) = transport::local_path(url) {
+        fs::copy(&path, partial).context(format!("Failed to copy {:?}", path))?;
+        return verify_partial(partial, expected_sha256);
+    }
+
+    // Resume from whatever an earlier attempt or run left behind
+    let offset = fs::metadata(partial).map(|m| m.len()).unwrap_or(0);
 
-    let mut response = reqwest::blocking::get(url)
-        .context("Failed to start download")?
+    let mut request = config.http()?.get(url);
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={}-", offset));
+    }
+
+    let response = request.send().context("Failed to start download")?;
+
+    if offset > 0 && response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        // Nothing left to fetch; the checksum decides whether the partial file is whole
+        return verify_partial(partial, expected_sha256);
+    }
+
+    let mut response = response
         .error_for_status()
         .context("Download URL returned error")?;
 
-    let total_size = response.content_length().unwrap_or(0);
-
-    let pb = ProgressBar::new(total_size);
+    // Servers without Range support answer 200 with the whole body
+    let resumed = offset > 0 && response.status() == StatusCode::PARTIAL_CONTENT;
+    let start = if resumed { offset } else { 0 };
+    let total_size = response
+        .content_length()
+        .map(|len| len + start)
+        .unwrap_or(0);
+
+    let pb = match multi {
+        Some(multi) => multi.add(ProgressBar::new(total_size)),
+        None => ProgressBar::new(total_size),
+    };
     pb.set_style(
         ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
+            .template("{spinner:.green} {prefix:<16} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
             .unwrap()
             .progress_chars("#>-"),
     );
-
-    let mut file = File::create(&temp_path).context("Failed to create temporary file")?;
-    let mut downloaded = 0u64;
+    pb.set_prefix(url.rsplit('/').next().unwrap_or(url).to_string());
+    pb.set_position(start);
+
+    let mut file = if resumed {
+        OpenOptions::new()
+            .append(true)
+            .open(partial)
+            .context("Failed to reopen partial download")?
+    } else {
+        File::create(partial).context("Failed to create partial download")?
+    };
+    let mut downloaded = start;
     let mut buffer = [0u8; 8192];
 
     loop {
@@ -68,38 +201,45 @@ fn download_file(url: &str, dest: &Path, expected_sha256: &str) -> Result<()> {
     pb.finish_with_message("Download complete");
     drop(file);
 
-    // Verify SHA256
-    verify_sha256(&temp_path, expected_sha256)?;
+    verify_partial(partial, expected_sha256)
+}
+
+/// Verify a finished download, discarding it if the checksum is wrong so the next attempt starts clean
+fn verify_partial(partial: &Path, expected_sha256: &str) -> Result<()> {
+    let result = verify_sha256(partial, expected_sha256);
+    if result.is_err() {
+        let _ = fs::remove_file(partial);
+    }
+    result
+}
 
-    // Atomic move to final destination
-    fs::rename(&temp_path, dest).context("Failed to move file to final destination")?;
+pub fn verify_sha256(path: &Path, expected: &str) -> Result<()> {
+    let computed = sha256_file(path)?;
+
+    if computed != expected {
+        anyhow::bail!("SHA256 mismatch: expected {}, got {}", expected, computed);
+    }
 
     Ok(())
 }
 
-fn verify_sha256(path: &Path, expected: &str) -> Result<()> {
-    let mut file = File::open(path)?;
+/// Hex sha256 of a file's contents
+pub fn sha256_file(path: &Path) -> Result<String> {
+    sha256_reader(File::open(path)?)
+}
+
+/// Hex sha256 of everything `reader` yields
+pub fn sha256_reader(mut reader: impl Read) -> Result<String> {
     let mut hasher = Sha256::new();
     let mut buffer = [0u8; 8192];
 
     loop {
-        let n = file.read(&mut buffer)?;
+        let n = reader.read(&mut buffer)?;
         if n == 0 {
             break;
         }
         hasher.update(&buffer[..n]);
     }
 
-    let hash = hasher.finalize();
-    let computed = format!("{:x}", hash);
-
-    if computed != expected {
-        anyhow::bail!(
-            "SHA256 mismatch: expected {}, got {}",
-            expected,
-            computed
-        );
-    }
-
-    Ok(())
+    Ok(format!("{:x}", hasher.finalize()))
 }
@@ -0,0 +1,180 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::output::{fail, Kind};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockMode {
+    /// Read-only commands; any number may run together
+    Shared,
+    /// Commands that change installed tools or the cache
+    Exclusive,
+}
+
+impl LockMode {
+    fn name(self) -> &'static str {
+        match self {
+            LockMode::Shared => "read-only",
+            LockMode::Exclusive => "exclusive",
+        }
+    }
+}
+
+/// Advisory lock on the cache dir, released when dropped
+pub struct Lock {
+    file: File,
+    /// File naming this process as a holder, next to the lock file
+    holder: PathBuf,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // Remove our PID first so nobody waiting is told about a finished process
+        let _ = fs::remove_file(&self.holder);
+        let _ = self.file.unlock();
+    }
+}
+
+pub fn acquire(config: &Config, mode: LockMode, wait: bool) -> Result<Lock> {
+    acquire_at(&config.lock_path(), mode, wait)
+}
+
+/// Lock `path` directly, for commands that run without a full configuration
+pub fn acquire_at(path: &Path, mode: LockMode, wait: bool) -> Result<Lock> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .context(format!("Failed to open lock file: {:?}", path))?;
+
+    let attempt = match mode {
+        LockMode::Shared => file.try_lock_shared(),
+        LockMode::Exclusive => file.try_lock(),
+    };
+
+    let holders = holders_dir(path);
+    match attempt {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) if wait => {
+            eprintln!(
+                "Waiting for {} to release {:?}...",
+                describe_holders(&holders),
+                path
+            );
+            match mode {
+                LockMode::Shared => file.lock_shared(),
+                LockMode::Exclusive => file.lock(),
+            }
+            .context("Failed to acquire lock")?;
+        }
+        Err(TryLockError::WouldBlock) => {
+            fail!(
+                Kind::Locked,
+                "Another gpm is running: {} holds {:?} (use --wait to wait for it)",
+                describe_holders(&holders),
+                path
+            );
+        }
+        Err(TryLockError::Error(e)) => {
+            return Err(e).context(format!("Failed to lock {:?}", path));
+        }
+    }
+
+    // Nobody else holds the lock now, so anything left behind is from a crashed run
+    if mode == LockMode::Exclusive {
+        let _ = fs::remove_dir_all(&holders);
+    }
+    fs::create_dir_all(&holders).context(format!("Failed to create {:?}", holders))?;
+    let holder = holders.join(std::process::id().to_string());
+    fs::write(&holder, mode.name()).context(format!("Failed to write {:?}", holder))?;
+
+    Ok(Lock { file, holder })
+}
+
+/// One file per running holder, named by its PID and holding its lock mode
+fn holders_dir(lock_path: &Path) -> PathBuf {
+    lock_path.with_extension("holders")
+}
+
+/// Name the processes holding the lock that recorded their PID and are still alive
+fn describe_holders(holders: &Path) -> String {
+    let mut alive: Vec<(u32, String)> = fs::read_dir(holders)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse::<u32>().ok()?;
+            if !Path::new(&format!("/proc/{}", pid)).exists() {
+                return None;
+            }
+            let mode = fs::read_to_string(entry.path()).unwrap_or_default();
+            Some((pid, mode.trim().to_string()))
+        })
+        .collect();
+    alive.sort();
+
+    match alive.as_slice() {
+        [] => "another gpm command".to_string(),
+        [(pid, mode)] if mode == LockMode::Shared.name() => {
+            format!("a read-only gpm command (PID {})", pid)
+        }
+        [(pid, _)] => format!("PID {}", pid),
+        many => format!(
+            "read-only gpm commands (PIDs {})",
+            many.iter()
+                .map(|(pid, _)| pid.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_every_live_holder() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gpm.lock");
+
+        let first = acquire_at(&path, LockMode::Shared, false).unwrap();
+        let holders = holders_dir(&path);
+        let own = std::process::id();
+        assert_eq!(
+            describe_holders(&holders),
+            format!("a read-only gpm command (PID {})", own)
+        );
+
+        // A PID that cannot be running is left out
+        fs::write(holders.join(u32::MAX.to_string()), "shared").unwrap();
+        assert_eq!(
+            describe_holders(&holders),
+            format!("a read-only gpm command (PID {})", own)
+        );
+
+        drop(first);
+        assert_eq!(describe_holders(&holders), "another gpm command");
+    }
+
+    #[test]
+    fn exclusive_holder_is_named_and_clears_stale_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gpm.lock");
+        let holders = holders_dir(&path);
+        fs::create_dir_all(&holders).unwrap();
+        fs::write(holders.join("1"), "exclusive").unwrap();
+
+        let lock = acquire_at(&path, LockMode::Exclusive, false).unwrap();
+        assert!(!holders.join("1").exists());
+        assert_eq!(
+            describe_holders(&holders),
+            format!("PID {}", std::process::id())
+        );
+        drop(lock);
+    }
+}
@@ -1,12 +1,31 @@
+mod archive;
 mod cli;
 mod commands;
 mod config;
 mod downloader;
+mod lock;
 mod manifest;
+mod mirrors;
+mod output;
+mod platform;
+mod signature;
+mod state;
+mod timestamp;
+mod transport;
+mod version;
+mod versions;
 
-use anyhow::Result;
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let cli = cli::parse();
-    commands::execute(cli)
+    output::set_format(cli.output);
+
+    match commands::execute(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            output::report_error(&e);
+            ExitCode::from(output::exit_code(&e))
+        }
+    }
 }
@@ -1,87 +1,801 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::fs;
-use std::time::SystemTime;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
 
-use crate::config::Config;
+use crate::config::{Config, Repo};
+use crate::mirrors;
+use crate::output::{fail, say, Failure, Kind};
+use crate::platform::DEFAULT_TARGET;
+use crate::signature;
+use crate::state::{self, InstalledTool};
+use crate::timestamp;
+use crate::transport::{self, Fetched, Validators};
+use crate::version::{self, Constraint};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Manifest {
     pub repo_version: String,
     pub updated_at: String,
-    pub tools: HashMap<String, Tool>,
+    /// Increases with every published manifest, so an older copy can be recognised
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial: Option<u64>,
+    /// RFC 3339 time after which the manifest must no longer be used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<String>,
+    pub tools: BTreeMap<String, Tool>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Tool {
     pub version: String,
     pub description: String,
-    pub url: String,
-    pub sha256: String,
+    /// Legacy single-artifact fields, describing a `DEFAULT_TARGET` build
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+    #[serde(default)]
     pub size: u64,
     pub build_type: String,
     pub license: String,
     pub source_url: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source_sha256: Option<String>,
+    /// Per-target builds keyed by target triple
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub targets: BTreeMap<String, Artifact>,
+    /// Earlier releases that can still be installed with `name@version`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<Release>,
+    /// Releases for subscribers of other channels than stable, keyed by channel name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub channels: BTreeMap<String, Release>,
+    /// Tools that must be installed first, as `name` or `name@constraint`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends: Vec<String>,
+    /// Tools installed along with this one unless `--no-recommends` is given
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub recommends: Vec<String>,
+}
+
+/// Channel every tool follows unless its repository or `--channel` says otherwise
+pub const STABLE_CHANNEL: &str = "stable";
+
+/// Another release of a tool with its own builds: an earlier one, or one on a channel
+///
+/// Description, license and build type are those of the current release.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Release {
+    pub version: String,
+    /// Legacy single-artifact fields, describing a `DEFAULT_TARGET` build
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub targets: BTreeMap<String, Artifact>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Artifact {
+    pub url: String,
+    pub sha256: String,
+    /// Alternative URLs serving the same file, tried after `url`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+    #[serde(default)]
+    pub size: u64,
+    /// Set when the artifact is an archive holding several files rather than a single binary
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive: Option<ArchiveLayout>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ArchiveLayout {
+    /// One of `tar.gz`, `tar.xz` or `tar.zst`
+    pub format: String,
+    /// Paths inside the archive linked into the install dir as executables
+    #[serde(default)]
+    pub bin: Vec<String>,
+    /// Directories inside the archive unpacked under the package prefix
+    /// (the whole archive is unpacked when empty)
+    #[serde(default)]
+    pub data: Vec<String>,
+}
+
+impl Tool {
+    /// Pick the build for the first of `targets` this tool provides
+    pub fn artifact_for(&self, targets: &[String]) -> Option<Artifact> {
+        for target in targets {
+            if let Some(artifact) = self.targets.get(target) {
+                return Some(artifact.clone());
+            }
+
+            if target == DEFAULT_TARGET {
+                if let (Some(url), Some(sha256)) = (&self.url, &self.sha256) {
+                    return Some(Artifact {
+                        url: url.clone(),
+                        sha256: sha256.clone(),
+                        mirrors: self.mirrors.clone(),
+                        size: self.size,
+                        archive: None,
+                    });
+                }
+            }
+        }
+
+        None
+    }
+
+    /// This release and every earlier one, newest first
+    pub fn releases(&self) -> Vec<Cow<'_, Tool>> {
+        let mut releases = vec![Cow::Borrowed(self)];
+        releases.extend(
+            self.history
+                .iter()
+                .map(|release| Cow::Owned(self.with_release(release))),
+        );
+
+        // Stable, so the current release wins over a history entry of the same version
+        releases.sort_by(|a, b| version::compare(&b.version, &a.version));
+        releases
+    }
+
+    /// The tool as a subscriber of `channel` sees it
+    ///
+    /// A channel release newer than the stable one becomes the current release,
+    /// with the stable one added to the history. Channels without a newer
+    /// release fall back to stable.
+    pub fn on_channel(&self, channel: &str) -> Cow<'_, Tool> {
+        let Some(release) = self.channels.get(channel) else {
+            return Cow::Borrowed(self);
+        };
+        if version::compare(&release.version, &self.version) != Ordering::Greater {
+            return Cow::Borrowed(self);
+        }
+
+        let mut history = vec![self.current_release()];
+        history.extend(self.history.iter().cloned());
+        Cow::Owned(Tool {
+            history,
+            ..self.with_release(release)
+        })
+    }
+
+    /// A copy of this entry describing `release` instead of the current release
+    fn with_release(&self, release: &Release) -> Tool {
+        Tool {
+            version: release.version.clone(),
+            url: release.url.clone(),
+            sha256: release.sha256.clone(),
+            mirrors: release.mirrors.clone(),
+            size: release.size,
+            source_url: release
+                .source_url
+                .clone()
+                .unwrap_or_else(|| self.source_url.clone()),
+            source_sha256: release.source_sha256.clone(),
+            targets: release.targets.clone(),
+            history: Vec::new(),
+            channels: BTreeMap::new(),
+            ..self.clone()
+        }
+    }
+
+    fn current_release(&self) -> Release {
+        Release {
+            version: self.version.clone(),
+            url: self.url.clone(),
+            sha256: self.sha256.clone(),
+            mirrors: self.mirrors.clone(),
+            size: self.size,
+            source_url: Some(self.source_url.clone()),
+            source_sha256: self.source_sha256.clone(),
+            targets: self.targets.clone(),
+        }
+    }
+
+    /// The newest release matching every constraint that has a build for `targets`
+    pub fn select(&self, constraints: &[&Constraint], targets: &[String]) -> Option<Cow<'_, Tool>> {
+        self.releases().into_iter().find(|release| {
+            constraints.iter().all(|c| c.matches(&release.version))
+                && release.artifact_for(targets).is_some()
+        })
+    }
+}
+
+/// The manifest of one repository
+#[derive(Debug)]
+pub struct RepoManifest {
+    pub repo: Repo,
+    pub manifest: Manifest,
+}
+
+/// Manifests of every repository that could be loaded, most preferred first
+#[derive(Debug)]
+pub struct Catalog {
+    pub repos: Vec<RepoManifest>,
+}
+
+/// A tool entry together with the repository it comes from
+#[derive(Debug, Clone, Copy)]
+pub struct Resolved<'a> {
+    pub repo: &'a Repo,
+    pub repo_version: &'a str,
+    pub tool: &'a Tool,
+}
+
+impl Catalog {
+    pub fn get(&self, repo_name: &str) -> Option<&RepoManifest> {
+        self.repos.iter().find(|r| r.repo.name == repo_name)
+    }
+
+    /// Every repository providing `tool_name`, most preferred first
+    pub fn providers<'a>(&'a self, tool_name: &'a str) -> impl Iterator<Item = Resolved<'a>> {
+        self.repos.iter().filter_map(move |r| {
+            r.manifest.tools.get(tool_name).map(|tool| Resolved {
+                repo: &r.repo,
+                repo_version: &r.manifest.repo_version,
+                tool,
+            })
+        })
+    }
+
+    /// The entry a bare `gpm install <name>` picks for a tool not yet installed:
+    /// the most preferred repository with a build for this host, otherwise the
+    /// most preferred repository listing the tool at all
+    pub fn preferred<'a>(&'a self, config: &Config, tool_name: &'a str) -> Option<Resolved<'a>> {
+        self.providers(tool_name)
+            .find(|r| r.tool.artifact_for(&config.targets).is_some())
+            .or_else(|| self.providers(tool_name).next())
+    }
+
+    /// Resolve a `name` or `repo/name` spec
+    ///
+    /// An explicit repository always wins. A tool already installed from a
+    /// repository that is still configured stays with it, so another repository
+    /// publishing the same name never takes it over. Anything else goes to
+    /// `preferred`.
+    pub fn resolve<'a>(
+        &'a self,
+        config: &Config,
+        spec: &'a str,
+        installed: Option<&InstalledTool>,
+    ) -> Result<Resolved<'a>> {
+        let (repo_name, tool_name) = match spec.split_once('/') {
+            Some((repo_name, tool_name)) => (Some(repo_name), tool_name),
+            None => (
+                installed
+                    .map(|t| t.repo.as_str())
+                    .filter(|name| config.repo(name).is_some()),
+                spec,
+            ),
+        };
+
+        let Some(repo_name) = repo_name else {
+            return self.preferred(config, tool_name).ok_or_else(|| {
+                Failure::new(
+                    Kind::NotFound,
+                    format!("Tool '{}' not found in any repository", tool_name),
+                )
+                .into()
+            });
+        };
+
+        if config.repo(repo_name).is_none() {
+            fail!(Kind::NotFound, "Unknown repository '{}'", repo_name);
+        }
+        let repo = self
+            .get(repo_name)
+            .ok_or_else(|| anyhow::anyhow!("Repository '{}' could not be loaded", repo_name))?;
+        let tool = repo.manifest.tools.get(tool_name).ok_or_else(|| {
+            Failure::new(
+                Kind::NotFound,
+                format!(
+                    "Tool '{}' not found in repository '{}'",
+                    tool_name, repo_name
+                ),
+            )
+        })?;
+
+        Ok(Resolved {
+            repo: &repo.repo,
+            repo_version: &repo.manifest.repo_version,
+            tool,
+        })
+    }
+
+    /// Tool names with a build for this host in at least one repository
+    pub fn available(&self, config: &Config) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .repos
+            .iter()
+            .flat_map(|r| r.manifest.tools.iter())
+            .filter(|(_, tool)| tool.artifact_for(&config.targets).is_some())
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// `repo/name` for every tool with a build for this host
+    pub fn qualified(&self, config: &Config) -> Vec<String> {
+        self.repos
+            .iter()
+            .flat_map(|r| {
+                r.manifest
+                    .tools
+                    .iter()
+                    .filter(|(_, tool)| tool.artifact_for(&config.targets).is_some())
+                    .map(|(name, _)| format!("{}/{}", r.repo.name, name))
+            })
+            .collect()
+    }
+}
+
+/// Load every configured repository
+///
+/// A repository that cannot be fetched or verified is reported and left out;
+/// the command only fails if none of them load.
+pub fn fetch_catalog(config: &Config) -> Result<Catalog> {
+    if config.repos.is_empty() {
+        fail!(
+            Kind::Repository,
+            "No repositories configured (see 'gpm repo add')"
+        );
+    }
+
+    let mut repos = Vec::new();
+    let mut first_error = None;
+
+    for repo in &config.repos {
+        match fetch_manifest(config, repo) {
+            Ok(manifest) => repos.push(RepoManifest {
+                repo: repo.clone(),
+                manifest,
+            }),
+            Err(e) => {
+                let e = e.context(Failure::new(
+                    Kind::Repository,
+                    format!("Failed to load repository '{}'", repo.name),
+                ));
+                if config.repos.len() > 1 {
+                    eprintln!("Warning: {:#}", e);
+                }
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    if repos.is_empty() {
+        return Err(first_error.unwrap());
+    }
+
+    Ok(Catalog { repos })
+}
+
+/// Where the cached manifest of a repository came from, and when
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheInfo {
+    /// URL the cached copy was fetched from; its validators only apply there
+    url: String,
+    #[serde(flatten)]
+    validators: Validators,
+    /// Unix time of the last download or successful revalidation
+    checked_at: u64,
+}
+
+/// A manifest as downloaded, verified and parsed
+struct Download {
+    data: Vec<u8>,
+    signature: Option<String>,
+    validators: Validators,
+    manifest: Manifest,
+}
+
+/// How a manifest was obtained
+pub enum Freshness {
+    /// Downloaded because it was new or changed
+    Fetched,
+    /// The server confirmed the cached copy is current
+    NotModified,
+    /// The cached copy was recent enough (or gpm is offline) and was not checked
+    Cached,
+    /// Refreshing failed, so the cached copy was used however old it is
+    Stale { error: anyhow::Error, age: u64 },
+}
+
+/// The manifest of `repo`, from the cache while it is younger than `cache_ttl`
+///
+/// If it cannot be refreshed, an older cached copy is used with a warning.
+pub fn fetch_manifest(config: &Config, repo: &Repo) -> Result<Manifest> {
+    let (manifest, freshness) = load_manifest(config, repo, false)?;
+
+    if let Freshness::Stale { error, age } = freshness {
+        eprintln!(
+            "Warning: Could not refresh repository '{}', using the cached manifest from {} ago: {:#}",
+            repo.name,
+            describe_age(age),
+            error
+        );
+    }
+
+    Ok(manifest)
+}
+
+/// Revalidate the manifest of `repo` with its server, ignoring `cache_ttl`
+pub fn refresh_manifest(config: &Config, repo: &Repo) -> Result<(Manifest, Freshness)> {
+    load_manifest(config, repo, true)
+}
+
+fn load_manifest(config: &Config, repo: &Repo, force: bool) -> Result<(Manifest, Freshness)> {
+    // Local repositories are cheap to re-read, so only remote ones use the cache
+    let local = transport::is_local(&repo.url);
+
+    let mut cache_error = None;
+    let cached = match load_cached_manifest(config, repo) {
+        Ok(cached) => cached,
+        Err(e) if config.offline && !local => {
+            return Err(e.context(format!(
+                "No usable cached manifest for repository '{}' (run 'gpm update' while online)",
+                repo.name
+            )));
+        }
+        Err(e) => {
+            if config.verbose {
+                eprintln!("Ignoring cached manifest for {}: {:#}", repo.name, e);
+            }
+            cache_error = Some(e);
+            None
+        }
+    };
+
+    if let Some((manifest, info)) = cached {
+        let age = state::now_unix().saturating_sub(info.checked_at);
+
+        // Offline, a cached manifest of any age is all there is
+        if !local && (config.offline || (!force && age < config.cache_ttl.as_secs())) {
+            if config.verbose {
+                say!("Using cached manifest for {}", repo.name);
+            }
+            return Ok((manifest, Freshness::Cached));
+        }
+
+        return Ok(match fetch_fresh(config, repo, Some(&info)) {
+            Ok(Some(manifest)) => (manifest, Freshness::Fetched),
+            Ok(None) => (manifest, Freshness::NotModified),
+            Err(error) => (manifest, Freshness::Stale { error, age }),
+        });
+    }
+
+    if config.offline && !local {
+        anyhow::bail!(
+            "No cached manifest for repository '{}' (run 'gpm update' while online)",
+            repo.name
+        );
+    }
+
+    let manifest = match fetch_fresh(config, repo, None) {
+        Ok(manifest) => {
+            manifest.context("Server reported an unchanged manifest that is not cached")?
+        }
+        Err(e) => {
+            return Err(match cache_error {
+                Some(cache_error) => e.context(format!(
+                    "The cached manifest is unusable ({:#}) and no fresh copy could be fetched",
+                    cache_error
+                )),
+                None => e,
+            });
+        }
+    };
+    Ok((manifest, Freshness::Fetched))
+}
+
+/// Download the manifest from the first mirror that serves a trusted copy
+///
+/// Returns `None` if the server confirms the cached copy described by `info`
+/// is still current.
+fn fetch_fresh(config: &Config, repo: &Repo, info: Option<&CacheInfo>) -> Result<Option<Manifest>> {
+    let urls = mirrors::candidates(config, repo, &repo.url, &[]);
+    let mut last_error = None;
+
+    for url in &urls {
+        // Validators only describe the copy served by the URL that sent them
+        let validators = info
+            .filter(|info| &info.url == url)
+            .map(|info| &info.validators);
+
+        match fetch_verified(config, repo, url, validators) {
+            Ok(Some(download)) => {
+                write_cache(config, repo, url, &download)?;
+                return Ok(Some(download.manifest));
+            }
+            Ok(None) => {
+                if config.verbose {
+                    say!("Manifest for {} is unchanged", repo.name);
+                }
+                if let Some(info) = info {
+                    let info = CacheInfo {
+                        url: url.clone(),
+                        validators: info.validators.clone(),
+                        checked_at: state::now_unix(),
+                    };
+                    write_atomic(
+                        &config.manifest_info_cache_path(&repo.name),
+                        &serde_json::to_vec_pretty(&info)?,
+                    )
+                    .context("Failed to write manifest cache")?;
+                }
+                return Ok(None);
+            }
+            Err(e) => {
+                if config.verbose && urls.len() > 1 {
+                    eprintln!("Manifest from {} rejected: {:#}", url, e);
+                }
+                last_error = Some(e);
+            }
+        }
+    }
+
+    Err(last_error.unwrap())
 }
 
-const CACHE_TTL_HOURS: i64 = 24;
+/// Cache the manifest exactly as signed, alongside its signature and validators
+fn write_cache(config: &Config, repo: &Repo, url: &str, download: &Download) -> Result<()> {
+    fs::create_dir_all(config.repo_cache_dir(&repo.name))
+        .context("Failed to create repository cache")?;
+    write_atomic(&config.manifest_cache_path(&repo.name), &download.data)
+        .context("Failed to write manifest cache")?;
 
-pub fn fetch_manifest(config: &Config) -> Result<Manifest> {
-    let cache_path = config.manifest_cache_path();
+    let signature_path = config.manifest_signature_cache_path(&repo.name);
+    match &download.signature {
+        Some(sig) => write_atomic(&signature_path, sig.as_bytes())
+            .context("Failed to write signature cache")?,
+        None if signature_path.exists() => fs::remove_file(&signature_path)?,
+        None => {}
+    }
 
-    // Check if cached manifest is fresh
-    if let Ok(cached) = load_cached_manifest(config) {
-        if config.verbose {
-            println!("Using cached manifest");
+    // Remember the newest serial; after an override the accepted one becomes the baseline
+    let serial = download.manifest.serial;
+    if config.allow_stale_manifest || serial > highest_serial(config, repo) {
+        let serial_path = config.manifest_serial_path(&repo.name);
+        match serial {
+            Some(serial) => write_atomic(&serial_path, format!("{}\n", serial).as_bytes())
+                .context("Failed to record manifest serial")?,
+            None if serial_path.exists() => fs::remove_file(&serial_path)?,
+            None => {}
         }
-        return Ok(cached);
     }
 
-    // Fetch fresh manifest
+    let info = CacheInfo {
+        url: url.to_string(),
+        validators: download.validators.clone(),
+        checked_at: state::now_unix(),
+    };
+    write_atomic(
+        &config.manifest_info_cache_path(&repo.name),
+        &serde_json::to_vec_pretty(&info)?,
+    )
+    .context("Failed to write manifest cache")
+}
+
+/// Download a manifest and its signature from `url` and verify them
+///
+/// Returns `None` if the server says the copy matching `validators` is current.
+fn fetch_verified(
+    config: &Config,
+    repo: &Repo,
+    url: &str,
+    validators: Option<&Validators>,
+) -> Result<Option<Download>> {
     if config.verbose {
-        println!("Fetching manifest from {}", config.manifest_url);
+        say!("Fetching manifest from {}", url);
     }
 
-    let response = reqwest::blocking::get(&config.manifest_url)
+    let (data, validators) = match transport::read_if_changed(config, url, validators)
         .context("Failed to fetch manifest")?
-        .error_for_status()
-        .context("Manifest URL returned error status")?;
+    {
+        Fetched::Data(data, validators) => (data, validators),
+        Fetched::NotModified => return Ok(None),
+        Fetched::Missing => anyhow::bail!("No manifest found at {}", url),
+    };
+    let signature = fetch_signature(config, url)?;
 
-    let manifest: Manifest = response.json().context("Failed to parse manifest JSON")?;
+    // Verify before parsing so an untrusted manifest is never acted upon
+    signature::verify(config, repo, &data, signature.as_deref())?;
 
-    // Cache the manifest
-    let json = serde_json::to_string_pretty(&manifest)?;
-    fs::write(&cache_path, json).context("Failed to write manifest cache")?;
+    let manifest: Manifest =
+        serde_json::from_slice(&data).context("Failed to parse manifest JSON")?;
+    check_freshness(config, repo, &manifest, false)?;
 
-    Ok(manifest)
+    Ok(Some(Download {
+        data,
+        signature,
+        validators,
+        manifest,
+    }))
+}
+
+/// Replace a cache file without readers ever seeing it half written
+///
+/// Read-only commands share the lock and may refresh the cache concurrently,
+/// so each process writes its own temporary file.
+fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let temp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&temp_path, data)?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+fn fetch_signature(config: &Config, manifest_url: &str) -> Result<Option<String>> {
+    let url = format!("{}.minisig", manifest_url);
+
+    let Some(data) = transport::read(config, &url).context("Failed to fetch manifest signature")?
+    else {
+        return Ok(None);
+    };
+    let text = String::from_utf8(data).context("Manifest signature is not valid text")?;
+
+    Ok(Some(text))
 }
 
-fn load_cached_manifest(config: &Config) -> Result<Manifest> {
-    let cache_path = config.manifest_cache_path();
+/// Load and re-verify the cached manifest of `repo`, if there is one
+fn load_cached_manifest(config: &Config, repo: &Repo) -> Result<Option<(Manifest, CacheInfo)>> {
+    let cache_path = config.manifest_cache_path(&repo.name);
 
     if !cache_path.exists() {
-        anyhow::bail!("Cache file does not exist");
+        return Ok(None);
+    }
+
+    let data = fs::read(&cache_path)?;
+    let signature = fs::read_to_string(config.manifest_signature_cache_path(&repo.name)).ok();
+    signature::verify(config, repo, &data, signature.as_deref())
+        .context("Cached manifest failed signature verification")?;
+    let manifest: Manifest =
+        serde_json::from_slice(&data).context("Failed to parse cached manifest")?;
+    check_freshness(config, repo, &manifest, true)?;
+
+    // Caches from before validators were kept count as checked when last written
+    let info = fs::read(config.manifest_info_cache_path(&repo.name))
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_else(|| CacheInfo {
+            url: String::new(),
+            validators: Validators::default(),
+            checked_at: fs::metadata(&cache_path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |age| age.as_secs()),
+        });
+
+    Ok(Some((manifest, info)))
+}
+
+/// Reject a manifest older than one already accepted from `repo`, or past its expiry
+///
+/// A mirror that is out of date or an attacker replaying an old signed manifest
+/// could otherwise hold back security fixes. `--allow-stale-manifest` turns this
+/// into a warning, e.g. after a repository reset its serial. So does expiry of a
+/// `cached` manifest in offline mode.
+fn check_freshness(config: &Config, repo: &Repo, manifest: &Manifest, cached: bool) -> Result<()> {
+    let mut problems = Vec::new();
+
+    // A manifest without a serial predates serials, so it is older than any of them
+    if let Some(seen) = highest_serial(config, repo) {
+        match manifest.serial {
+            Some(serial) if serial >= seen => {}
+            Some(serial) => problems.push(format!(
+                "its serial {} is older than serial {} already seen",
+                serial, seen
+            )),
+            None => problems.push(format!(
+                "it has no serial but serial {} was already seen",
+                seen
+            )),
+        }
     }
 
-    // Check cache age
-    let metadata = fs::metadata(&cache_path)?;
-    let modified = metadata.modified()?;
-    let age = SystemTime::now().duration_since(modified)?;
+    if let Some(expires) = &manifest.expires {
+        match timestamp::parse(expires) {
+            Some(expiry) if state::now_unix() < expiry => {}
+            // Offline there is no way to get a newer one, and this copy was fine when fetched
+            Some(_) if cached && config.offline => eprintln!(
+                "Warning: Using the cached manifest of repository '{}' although it expired at {} (offline)",
+                repo.name, expires
+            ),
+            Some(_) => problems.push(format!("it expired at {}", expires)),
+            None => problems.push(format!("its expiry '{}' is not a valid timestamp", expires)),
+        }
+    }
 
-    if age.as_secs() > (CACHE_TTL_HOURS * 3600) as u64 {
-        anyhow::bail!("Cache is stale");
+    if problems.is_empty() {
+        return Ok(());
     }
 
-    // Load and parse
-    let json = fs::read_to_string(&cache_path)?;
-    let manifest: Manifest = serde_json::from_str(&json)?;
+    let problems = problems.join(" and ");
+    if config.allow_stale_manifest {
+        eprintln!(
+            "Warning: Accepting stale manifest of repository '{}': {}",
+            repo.name, problems
+        );
+        return Ok(());
+    }
 
-    Ok(manifest)
+    anyhow::bail!(
+        "Refusing stale manifest of repository '{}': {} (use --allow-stale-manifest to accept it anyway)",
+        repo.name,
+        problems
+    )
+}
+
+fn highest_serial(config: &Config, repo: &Repo) -> Option<u64> {
+    fs::read_to_string(config.manifest_serial_path(&repo.name))
+        .ok()
+        .and_then(|text| text.trim().parse().ok())
 }
 
-pub fn find_tool<'a>(manifest: &'a Manifest, tool_name: &str) -> Option<&'a Tool> {
-    manifest.tools.get(tool_name)
+/// A number of seconds as a rough human-readable age
+pub fn describe_age(secs: u64) -> String {
+    match secs {
+        0..120 => format!("{} seconds", secs),
+        120..7200 => format!("{} minutes", secs / 60),
+        7200..172800 => format!("{} hours", secs / 3600),
+        _ => format!("{} days", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+impl Catalog {
+    /// One repository per `(name, tools)` pair, most preferred first, with `tools`
+    /// written as in a manifest; fields every tool needs default to placeholders
+    pub fn for_tests(repos: &[(&str, serde_json::Value)]) -> Catalog {
+        let repos = repos
+            .iter()
+            .map(|(name, tools)| {
+                let mut tools = tools.clone();
+                for tool in tools.as_object_mut().unwrap().values_mut() {
+                    let tool = tool.as_object_mut().unwrap();
+                    for field in ["description", "build_type", "license", "source_url"] {
+                        tool.entry(field).or_insert_with(|| "test".into());
+                    }
+                }
+                RepoManifest {
+                    repo: Repo {
+                        name: name.to_string(),
+                        url: format!("https://example.invalid/{}/manifest.json", name),
+                        priority: 0,
+                        key: None,
+                        mirrors: Vec::new(),
+                        channel: None,
+                    },
+                    manifest: Manifest {
+                        repo_version: "1.0.0".to_string(),
+                        updated_at: "2026-01-01T00:00:00Z".to_string(),
+                        serial: None,
+                        expires: None,
+                        tools: serde_json::from_value(tools).unwrap(),
+                    },
+                }
+            })
+            .collect();
+        Catalog { repos }
+    }
 }
@@ -0,0 +1,102 @@
+use reqwest::Url;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, MirrorStrategy, Repo};
+use crate::output::say;
+use crate::transport;
+
+/// How long a latency probe may take before the host counts as unreachable
+const PROBE_TIMEOUT_SECS: u64 = 5;
+
+/// Every URL `url` can be fetched from, in the order to try them
+///
+/// The repository's mirror base URLs come first (a LAN mirror should win over
+/// the internet), then `url` itself, then the alternatives the manifest lists.
+/// A mirror base serves each file under its own file name, and relative URLs are
+/// resolved against the repository's manifest. Offline, only local files are
+/// candidates. Any of them may be wrong or tampered with; the caller's checksum
+/// or signature check decides.
+pub fn candidates(config: &Config, repo: &Repo, url: &str, alternatives: &[String]) -> Vec<String> {
+    let url = &transport::join(&repo.url, url);
+
+    let mut ordered = Vec::new();
+    if let Some(file_name) = file_name(url) {
+        for base in &repo.mirrors {
+            ordered.push(format!("{}/{}", base.trim_end_matches('/'), file_name));
+        }
+    }
+    ordered.push(url.to_string());
+    ordered.extend(
+        alternatives
+            .iter()
+            .map(|alt| transport::join(&repo.url, alt)),
+    );
+
+    let mut urls: Vec<String> = Vec::new();
+    for url in ordered {
+        if config.offline && !transport::is_local(&url) {
+            continue;
+        }
+        if !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+
+    if config.mirror_strategy == MirrorStrategy::Latency && urls.len() > 1 {
+        // Stable sort keeps the configured order between equally fast or unreachable hosts
+        let latencies: Vec<Option<Duration>> = urls.iter().map(|u| latency(config, u)).collect();
+        let mut ranked: Vec<(Option<Duration>, String)> = latencies.into_iter().zip(urls).collect();
+        ranked.sort_by_key(|(latency, _)| latency.unwrap_or(Duration::MAX));
+
+        if config.verbose {
+            for (latency, url) in &ranked {
+                match latency {
+                    Some(latency) => say!("  {} ms  {}", latency.as_millis(), url),
+                    None => say!("  unreachable  {}", url),
+                }
+            }
+        }
+
+        urls = ranked.into_iter().map(|(_, url)| url).collect();
+    }
+
+    urls
+}
+
+/// Last path segment of a URL
+pub fn file_name(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let name = url.path_segments()?.next_back()?;
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Round-trip time of a HEAD request, measured once per host and run
+fn latency(config: &Config, url: &str) -> Option<Duration> {
+    static PROBED: OnceLock<Mutex<HashMap<String, Option<Duration>>>> = OnceLock::new();
+
+    if transport::is_local(url) {
+        return Some(Duration::ZERO);
+    }
+
+    let origin = Url::parse(url).ok()?.origin().ascii_serialization();
+    let probed = PROBED.get_or_init(Default::default);
+
+    if let Some(latency) = probed.lock().unwrap().get(&origin) {
+        return *latency;
+    }
+
+    let start = Instant::now();
+    let reachable = config.http().ok().is_some_and(|client| {
+        client
+            .head(url)
+            .timeout(Duration::from_secs(PROBE_TIMEOUT_SECS))
+            .send()
+            .is_ok()
+    });
+    let latency = reachable.then(|| start.elapsed());
+
+    probed.lock().unwrap().insert(origin, latency);
+    latency
+}
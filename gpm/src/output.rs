@@ -0,0 +1,278 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+/// How command results are printed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Tables and messages for people
+    #[default]
+    Text,
+    /// One JSON document on stdout
+    Json,
+    /// Tab-separated rows with a header line on stdout
+    Tsv,
+}
+
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+/// Whether a command already printed its document, so a failure must not add another
+static EMITTED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_format(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+}
+
+pub fn format() -> OutputFormat {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+pub fn is_text() -> bool {
+    format() == OutputFormat::Text
+}
+
+/// Print a status line: to stdout for people, to stderr when stdout carries data
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if $crate::output::is_text() {
+            println!($($arg)*);
+        } else {
+            eprintln!($($arg)*);
+        }
+    };
+}
+pub(crate) use say;
+
+/// Print the JSON document of a command
+pub fn emit_json<T: Serialize>(document: &T) {
+    EMITTED.store(true, Ordering::Relaxed);
+    if let Some(json) = json_text(document) {
+        print!("{}", json);
+    }
+}
+
+fn json_text<T: Serialize>(document: &T) -> Option<String> {
+    match serde_json::to_string_pretty(document) {
+        Ok(json) => Some(json + "\n"),
+        Err(e) => {
+            eprintln!("Failed to serialize output: {}", e);
+            None
+        }
+    }
+}
+
+/// Print a header line and one tab-separated line per row
+///
+/// Tabs and newlines inside fields are replaced by spaces so every row stays one line.
+pub fn emit_tsv(header: &[&str], rows: &[Vec<String>]) {
+    EMITTED.store(true, Ordering::Relaxed);
+    print!("{}", tsv_text(header, rows));
+}
+
+fn tsv_text(header: &[&str], rows: &[Vec<String>]) -> String {
+    let mut text = header.join("\t") + "\n";
+    for row in rows {
+        let fields: Vec<String> = row
+            .iter()
+            .map(|field| field.replace(['\t', '\n', '\r'], " "))
+            .collect();
+        text.push_str(&fields.join("\t"));
+        text.push('\n');
+    }
+    text
+}
+
+/// What kind of failure ended a run, and the exit status reporting it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Bad arguments, or patterns that match nothing
+    Usage = 2,
+    /// Some of the tools a command worked on failed; the others succeeded
+    Partial = 3,
+    /// The named tool or repository does not exist or is not installed
+    NotFound = 4,
+    /// No repository manifest could be fetched, verified or loaded from the cache
+    Repository = 5,
+    /// Another gpm holds the lock
+    Locked = 6,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Usage => "usage",
+            Kind::Partial => "partial",
+            Kind::NotFound => "not_found",
+            Kind::Repository => "repository",
+            Kind::Locked => "locked",
+        }
+    }
+}
+
+/// An error with a known kind; anything else exits with status 1
+#[derive(Debug)]
+pub struct Failure {
+    pub kind: Kind,
+    message: String,
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+impl Failure {
+    pub fn new(kind: Kind, message: impl Into<String>) -> Self {
+        Failure {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+/// Shorthand for `anyhow::bail!` with a failure kind
+macro_rules! fail {
+    ($kind:expr, $($arg:tt)*) => {
+        return Err(anyhow::Error::new($crate::output::Failure::new($kind, format!($($arg)*))))
+    };
+}
+pub(crate) use fail;
+
+/// Exit status for an error: its outermost `Failure` kind, else 1
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    error
+        .downcast_ref::<Failure>()
+        .map_or(1, |failure| failure.kind as u8)
+}
+
+#[derive(Serialize)]
+struct ErrorDocument {
+    error: ErrorInfo,
+}
+
+#[derive(Serialize)]
+struct ErrorInfo {
+    code: u8,
+    kind: &'static str,
+    message: String,
+}
+
+/// Report a failed run on stderr, and as a document on stdout if none was printed yet
+pub fn report_error(error: &anyhow::Error) {
+    eprintln!("Error: {:?}", error);
+
+    if let Some(document) = error_document(format(), &EMITTED, error) {
+        print!("{}", document);
+    }
+}
+
+/// The document describing `error`, unless `emitted` says one was already printed
+fn error_document(
+    format: OutputFormat,
+    emitted: &AtomicBool,
+    error: &anyhow::Error,
+) -> Option<String> {
+    if format == OutputFormat::Text || emitted.swap(true, Ordering::Relaxed) {
+        return None;
+    }
+
+    let code = exit_code(error);
+    let kind = error
+        .downcast_ref::<Failure>()
+        .map_or("error", |failure| failure.kind.name());
+    match format {
+        OutputFormat::Text => None,
+        OutputFormat::Json => json_text(&ErrorDocument {
+            error: ErrorInfo {
+                code,
+                kind,
+                message: format!("{:#}", error),
+            },
+        }),
+        OutputFormat::Tsv => Some(tsv_text(
+            &["code", "kind", "message"],
+            &[vec![
+                code.to_string(),
+                kind.to_string(),
+                format!("{:#}", error),
+            ]],
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    fn failure(kind: Kind) -> anyhow::Error {
+        anyhow::Error::new(Failure::new(kind, "inner"))
+    }
+
+    #[test]
+    fn failures_keep_their_exit_code_under_context() {
+        for (kind, code) in [
+            (Kind::Usage, 2),
+            (Kind::Partial, 3),
+            (Kind::NotFound, 4),
+            (Kind::Repository, 5),
+            (Kind::Locked, 6),
+        ] {
+            let error = Err::<(), _>(failure(kind))
+                .context("Failed to install tool")
+                .context("outer")
+                .unwrap_err();
+            assert_eq!(exit_code(&error), code, "{:?}", kind);
+        }
+
+        assert_eq!(exit_code(&anyhow::anyhow!("plain")), 1);
+        assert_eq!(
+            exit_code(&anyhow::anyhow!("plain").context("with context")),
+            1
+        );
+    }
+
+    #[test]
+    fn the_outermost_failure_decides_the_exit_code() {
+        let error = Err::<(), _>(failure(Kind::NotFound))
+            .context(Failure::new(Kind::Partial, "some tools failed"))
+            .unwrap_err();
+
+        assert_eq!(exit_code(&error), 3);
+    }
+
+    #[test]
+    fn errors_are_reported_as_one_json_document() {
+        let emitted = AtomicBool::new(false);
+        let error = Err::<(), _>(failure(Kind::Locked))
+            .context("Failed to lock")
+            .unwrap_err();
+
+        let document = error_document(OutputFormat::Json, &emitted, &error).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&document).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "error": {"code": 6, "kind": "locked", "message": "Failed to lock: inner"}
+            })
+        );
+
+        // Nothing more once a document was printed, by a command or an earlier report
+        assert!(error_document(OutputFormat::Json, &emitted, &error).is_none());
+        let emitted = AtomicBool::new(true);
+        assert!(error_document(OutputFormat::Json, &emitted, &error).is_none());
+    }
+
+    #[test]
+    fn errors_are_reported_as_one_tsv_row_and_not_in_text() {
+        let error = anyhow::anyhow!("broken\tline");
+
+        let document = error_document(OutputFormat::Tsv, &AtomicBool::new(false), &error).unwrap();
+        assert_eq!(document, "code\tkind\tmessage\n1\terror\tbroken line\n");
+
+        let emitted = AtomicBool::new(false);
+        assert!(error_document(OutputFormat::Text, &emitted, &error).is_none());
+    }
+}
@@ -0,0 +1,41 @@
+use std::process::Command;
+
+/// Target triple the legacy top-level `url`/`sha256` manifest fields describe
+pub const DEFAULT_TARGET: &str = "x86_64-unknown-linux-musl";
+
+/// Target triples whose static builds run on this host, most preferred first
+pub fn host_targets() -> Vec<String> {
+    let arch = detect_arch();
+
+    match arch.as_str() {
+        "armv7" => vec![
+            "armv7-unknown-linux-musleabihf".to_string(),
+            "armv7-unknown-linux-gnueabihf".to_string(),
+        ],
+        _ => vec![
+            format!("{}-unknown-linux-musl", arch),
+            format!("{}-unknown-linux-gnu", arch),
+        ],
+    }
+}
+
+/// Machine architecture reported by the kernel, normalised to Rust's naming
+fn detect_arch() -> String {
+    let machine = Command::new("uname")
+        .arg("-m")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|machine| !machine.is_empty())
+        .unwrap_or_else(|| std::env::consts::ARCH.to_string());
+
+    match machine.as_str() {
+        "amd64" => "x86_64".to_string(),
+        "arm64" => "aarch64".to_string(),
+        // armv8l is a 32-bit userland on a 64-bit ARM kernel, so it runs armv7 builds
+        m if m.starts_with("armv7") || m == "armv8l" => "armv7".to_string(),
+        "riscv64" => "riscv64gc".to_string(),
+        _ => machine,
+    }
+}
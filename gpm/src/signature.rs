@@ -0,0 +1,404 @@
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use blake2::digest::consts::U32;
+use blake2::{Blake2b, Blake2b512, Digest};
+use ed25519_dalek::{Signer, SigningKey};
+use minisign_verify::{PublicKey, Signature};
+use std::fs;
+use std::path::Path;
+
+use crate::config::{Config, Repo};
+use crate::output::say;
+
+/// Public keys baked in at build time, comma separated, in minisign base64 form
+const BUILTIN_KEYS: Option<&str> = option_env!("GPM_MANIFEST_PUBKEY");
+
+/// Collect the trusted keys: built-in ones plus every `*.pub` file in the trusted-keys dir
+pub fn trusted_keys(config: &Config) -> Result<Vec<PublicKey>> {
+    let mut keys = Vec::new();
+
+    if let Some(builtin) = BUILTIN_KEYS {
+        for encoded in builtin.split(',').map(str::trim).filter(|k| !k.is_empty()) {
+            let key = PublicKey::from_base64(encoded)
+                .context(format!("Invalid built-in manifest key: {}", encoded))?;
+            keys.push(key);
+        }
+    }
+
+    let keys_dir = config.trusted_keys_dir();
+    if keys_dir.exists() {
+        for entry in fs::read_dir(&keys_dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "pub") {
+                let key = PublicKey::from_file(&path)
+                    .context(format!("Invalid manifest key file: {:?}", path))?;
+                keys.push(key);
+            }
+        }
+    }
+
+    Ok(keys)
+}
+
+/// Read a repository key given inline in base64 or as a path to a `.pub` file
+///
+/// Base64 can contain `/` too, so a value with a slash only counts as a path
+/// when it does not decode as a key.
+pub fn parse_key(key: &str) -> Result<PublicKey> {
+    let is_path = key.ends_with(".pub")
+        || key.starts_with(['/', '.'])
+        || Path::new(key).exists()
+        || (key.contains('/') && PublicKey::from_base64(key).is_err());
+    if is_path {
+        return PublicKey::from_file(key).context(format!("Invalid manifest key file: {}", key));
+    }
+
+    PublicKey::from_base64(key).context(format!("Invalid manifest key: {}", key))
+}
+
+/// Check a detached minisign signature over `data` against the keys trusted for `repo`
+///
+/// A repository with its own key trusts only that key; the others trust the
+/// built-in keys and the trusted-keys directory.
+pub fn verify(config: &Config, repo: &Repo, data: &[u8], signature: Option<&str>) -> Result<()> {
+    let result = verify_strict(config, repo, data, signature);
+
+    if config.insecure {
+        if let Err(e) = result {
+            eprintln!("Warning: {:#} (continuing because --insecure was given)", e);
+        }
+        return Ok(());
+    }
+
+    result
+}
+
+fn verify_strict(config: &Config, repo: &Repo, data: &[u8], signature: Option<&str>) -> Result<()> {
+    let signature = signature.ok_or_else(|| anyhow::anyhow!("Manifest is not signed"))?;
+    let signature = Signature::decode(signature).context("Malformed manifest signature")?;
+
+    let keys = match &repo.key {
+        Some(key) => vec![parse_key(key)?],
+        None => trusted_keys(config)?,
+    };
+    if keys.is_empty() {
+        anyhow::bail!(
+            "No trusted manifest keys configured (add a minisign .pub file to {:?})",
+            config.trusted_keys_dir()
+        );
+    }
+
+    if keys
+        .iter()
+        .any(|key| key.verify(data, &signature, false).is_ok())
+    {
+        if config.verbose {
+            say!(
+                "Manifest signature for {} verified ({})",
+                repo.name,
+                signature.trusted_comment()
+            );
+        }
+        return Ok(());
+    }
+
+    anyhow::bail!("Manifest signature does not match any trusted key")
+}
+
+/// Length of a decoded minisign secret key
+const SECRET_KEY_LEN: usize = 158;
+
+/// Sign `data` with a minisign secret key file and return the `.minisig` contents
+///
+/// Password-protected keys are unlocked with `GPM_SIGN_PASSWORD`.
+pub fn sign(key_path: &Path, data: &[u8], trusted_comment: &str) -> Result<String> {
+    let password = std::env::var("GPM_SIGN_PASSWORD").ok();
+    sign_with(key_path, password.as_deref(), data, trusted_comment)
+}
+
+fn sign_with(
+    key_path: &Path,
+    password: Option<&str>,
+    data: &[u8],
+    trusted_comment: &str,
+) -> Result<String> {
+    let text = fs::read_to_string(key_path)
+        .context(format!("Failed to read secret key {:?}", key_path))?;
+    let encoded = text
+        .lines()
+        .find(|line| !line.starts_with("untrusted comment:") && !line.trim().is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Secret key file {:?} is empty", key_path))?;
+    let raw = BASE64
+        .decode(encoded.trim())
+        .context("Malformed minisign secret key")?;
+
+    if raw.len() != SECRET_KEY_LEN || &raw[0..2] != b"Ed" || &raw[4..6] != b"B2" {
+        anyhow::bail!("{:?} is not a minisign secret key", key_path);
+    }
+
+    // Layout: algorithms, kdf salt and limits, then key id, key pair and checksum
+    let mut keynum = raw[54..].to_vec();
+    match &raw[2..4] {
+        [0, 0] => {}
+        b"Sc" => {
+            let password = password.ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{:?} is password protected; set GPM_SIGN_PASSWORD",
+                    key_path
+                )
+            })?;
+            let opslimit = u64::from_le_bytes(raw[38..46].try_into().unwrap());
+            let memlimit = u64::from_le_bytes(raw[46..54].try_into().unwrap());
+            let stream = scrypt_stream(password.as_bytes(), &raw[6..38], opslimit, memlimit)?;
+            for (byte, key) in keynum.iter_mut().zip(stream) {
+                *byte ^= key;
+            }
+        }
+        _ => anyhow::bail!("Unsupported key derivation in {:?}", key_path),
+    }
+
+    let (key_id, rest) = keynum.split_at(8);
+    let (keypair, checksum) = rest.split_at(64);
+
+    let mut hasher = Blake2b::<U32>::new();
+    hasher.update(&raw[0..2]);
+    hasher.update(key_id);
+    hasher.update(keypair);
+    if hasher.finalize().as_slice() != checksum {
+        anyhow::bail!("Wrong password for {:?}, or the key is corrupt", key_path);
+    }
+
+    let signing_key = SigningKey::from_keypair_bytes(keypair.try_into().unwrap())
+        .context("Invalid minisign secret key")?;
+
+    // Prehashed signature over the data, then a global signature binding the trusted comment
+    let signature = signing_key.sign(&Blake2b512::digest(data)).to_bytes();
+    let mut global = signature.to_vec();
+    global.extend_from_slice(trusted_comment.as_bytes());
+    let global_signature = signing_key.sign(&global).to_bytes();
+
+    let mut signature_line = b"ED".to_vec();
+    signature_line.extend_from_slice(key_id);
+    signature_line.extend_from_slice(&signature);
+
+    Ok(format!(
+        "untrusted comment: signature from gpm secret key\n{}\ntrusted comment: {}\n{}\n",
+        BASE64.encode(signature_line),
+        trusted_comment,
+        BASE64.encode(global_signature)
+    ))
+}
+
+/// Key stream protecting an encrypted minisign key
+fn scrypt_stream(password: &[u8], salt: &[u8], opslimit: u64, memlimit: u64) -> Result<Vec<u8>> {
+    let (log_n, r, p) = scrypt_params(opslimit, memlimit);
+
+    // `len` only matters for PHC strings; the output length is the buffer's
+    let params = scrypt::Params::new(log_n, r, p, scrypt::Params::RECOMMENDED_LEN)
+        .map_err(|e| anyhow::anyhow!("Invalid key derivation parameters: {}", e))?;
+    let mut stream = vec![0u8; 104];
+    scrypt::scrypt(password, salt, &params, &mut stream)
+        .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
+
+    Ok(stream)
+}
+
+/// scrypt `(log2 N, r, p)` for minisign's limits, chosen the way libsodium does
+fn scrypt_params(opslimit: u64, memlimit: u64) -> (u8, u32, u32) {
+    let opslimit = opslimit.max(32768);
+    let r: u32 = 8;
+    let log_n_for = |max_n: u64| {
+        (1..63)
+            .find(|log_n| (1u64 << log_n) > max_n / 2)
+            .unwrap_or(63)
+    };
+
+    if opslimit < memlimit / 32 {
+        (log_n_for(opslimit / (u64::from(r) * 4)), r, 1)
+    } else {
+        let log_n = log_n_for(memlimit / (u64::from(r) * 128));
+        let max_rp = ((opslimit / 4) >> log_n).min(0x3fff_ffff) as u32;
+        (log_n, r, max_rp / r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &str = "correct horse battery staple";
+
+    /// Write a minisign secret key for a fixed seed, encrypted when `password` is given
+    fn write_secret_key(path: &Path, password: Option<&str>) -> PublicKey {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let key_id = *b"gpmtest!";
+        let keypair = signing_key.to_keypair_bytes();
+
+        let mut keynum = key_id.to_vec();
+        keynum.extend_from_slice(&keypair);
+        let mut hasher = Blake2b::<U32>::new();
+        hasher.update(b"Ed");
+        hasher.update(key_id);
+        hasher.update(keypair);
+        keynum.extend_from_slice(&hasher.finalize());
+
+        // Cheap limits keep the test fast: N = 2^10, r = 8, p = 1
+        let (salt, opslimit, memlimit) = ([3u8; 32], 32768u64, 16u64 << 20);
+        let kdf: &[u8] = match password {
+            Some(password) => {
+                let stream = scrypt_stream(password.as_bytes(), &salt, opslimit, memlimit).unwrap();
+                for (byte, key) in keynum.iter_mut().zip(stream) {
+                    *byte ^= key;
+                }
+                b"Sc"
+            }
+            None => &[0, 0],
+        };
+
+        let mut raw = b"Ed".to_vec();
+        raw.extend_from_slice(kdf);
+        raw.extend_from_slice(b"B2");
+        raw.extend_from_slice(&salt);
+        raw.extend_from_slice(&opslimit.to_le_bytes());
+        raw.extend_from_slice(&memlimit.to_le_bytes());
+        raw.extend_from_slice(&keynum);
+        assert_eq!(raw.len(), SECRET_KEY_LEN);
+        fs::write(
+            path,
+            format!(
+                "untrusted comment: test secret key\n{}\n",
+                BASE64.encode(raw)
+            ),
+        )
+        .unwrap();
+
+        let mut public = b"Ed".to_vec();
+        public.extend_from_slice(&key_id);
+        public.extend_from_slice(signing_key.verifying_key().as_bytes());
+        PublicKey::from_base64(&BASE64.encode(public)).unwrap()
+    }
+
+    fn verifies(key: &PublicKey, data: &[u8], minisig: &str) -> bool {
+        let signature = Signature::decode(minisig).unwrap();
+        key.verify(data, &signature, false).is_ok()
+    }
+
+    #[test]
+    fn signs_with_encrypted_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.key");
+        let key = write_secret_key(&path, Some(PASSWORD));
+
+        let data = b"{\"tools\": {}}";
+        let minisig = sign_with(&path, Some(PASSWORD), data, "snapshot 1").unwrap();
+        assert!(verifies(&key, data, &minisig));
+        assert!(!verifies(&key, b"{}", &minisig));
+        assert_eq!(
+            Signature::decode(&minisig).unwrap().trusted_comment(),
+            "snapshot 1"
+        );
+    }
+
+    #[test]
+    fn signs_with_unencrypted_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.key");
+        let key = write_secret_key(&path, None);
+
+        let minisig = sign_with(&path, None, b"data", "comment").unwrap();
+        assert!(verifies(&key, b"data", &minisig));
+    }
+
+    #[test]
+    fn wrong_or_missing_password_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.key");
+        write_secret_key(&path, Some(PASSWORD));
+
+        let wrong = sign_with(&path, Some("hunter2"), b"data", "comment").unwrap_err();
+        assert!(wrong.to_string().starts_with("Wrong password"), "{}", wrong);
+
+        let missing = sign_with(&path, None, b"data", "comment").unwrap_err();
+        assert!(
+            missing.to_string().contains("GPM_SIGN_PASSWORD"),
+            "{}",
+            missing
+        );
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_secret_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.pub");
+        fs::write(&path, "untrusted comment: x\nRWQAAAAAAAAAAA==\n").unwrap();
+
+        assert!(sign_with(&path, None, b"data", "comment").is_err());
+    }
+
+    #[test]
+    fn scrypt_parameters_match_libsodium() {
+        // crypto_pwhash_scryptsalsa208sha256 interactive limits
+        assert_eq!(scrypt_params(524288, 16777216), (14, 8, 1));
+        // Its sensitive limits, which minisign uses for new keys
+        assert_eq!(scrypt_params(33554432, 1073741824), (20, 8, 1));
+        // Limits below libsodium's minimum are raised to it
+        assert_eq!(scrypt_params(0, 16777216), (10, 8, 1));
+    }
+
+    /// A public key in minisign base64 form that contains a `/`
+    fn public_key_with_slash() -> String {
+        (0u8..)
+            .map(|seed| {
+                let mut public = b"Edgpmtest!".to_vec();
+                public.extend_from_slice(
+                    SigningKey::from_bytes(&[seed; 32])
+                        .verifying_key()
+                        .as_bytes(),
+                );
+                BASE64.encode(public)
+            })
+            .find(|encoded| encoded.contains('/'))
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_keys_inline_or_from_relative_and_absolute_paths() {
+        let encoded = public_key_with_slash();
+        assert!(parse_key(&encoded).is_ok());
+
+        // Relative to the working directory, which is the crate root under cargo test
+        let dir = tempfile::Builder::new().tempdir_in("target").unwrap();
+        let relative = dir
+            .path()
+            .strip_prefix(std::env::current_dir().unwrap())
+            .unwrap_or(dir.path())
+            .to_path_buf();
+        let contents = format!("untrusted comment: repo key\n{}\n", encoded);
+        for name in ["repo.pub", "repo.key"] {
+            fs::write(dir.path().join(name), &contents).unwrap();
+            let relative = relative.join(name);
+            let relative = relative.to_str().unwrap();
+            assert!(!relative.starts_with('/'), "{}", relative);
+
+            for form in [relative.to_string(), format!("./{}", relative)] {
+                assert!(parse_key(&form).is_ok(), "{}", form);
+            }
+            let absolute = dir.path().join(name);
+            assert!(parse_key(absolute.to_str().unwrap()).is_ok());
+        }
+
+        let error = parse_key("keys/missing.pub").unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("Invalid manifest key file"),
+            "{:#}",
+            error
+        );
+        let error = parse_key("not a key").unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("Invalid manifest key:"),
+            "{:#}",
+            error
+        );
+    }
+}
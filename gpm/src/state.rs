@@ -0,0 +1,215 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{Config, DEFAULT_REPO};
+use crate::manifest::ArchiveLayout;
+use crate::version::Constraint;
+
+/// Record of every tool gpm has installed, persisted as JSON in the cache dir
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct InstalledDb {
+    pub tools: BTreeMap<String, InstalledTool>,
+    /// Previously installed versions kept in the versions store, oldest first
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub history: BTreeMap<String, Vec<InstalledTool>>,
+    /// Versions tools are pinned to by `gpm pin`, whether or not they are installed
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pins: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InstalledTool {
+    pub version: String,
+    pub sha256: String,
+    pub url: String,
+    /// Seconds since the Unix epoch
+    pub installed_at: u64,
+    /// Repository the tool was installed from
+    #[serde(default = "default_repo")]
+    pub repo: String,
+    /// `repo_version` of the manifest the tool was installed from
+    pub repo_version: String,
+    /// Size in bytes of the installed binary, or of the archive a package came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Layout of the archive the tool was unpacked from, if it is a package
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive: Option<ArchiveLayout>,
+    /// Links and directories a package owns outside `install_dir/<name>`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<PathBuf>,
+    /// Channel chosen with `gpm install --channel`; unset follows the repository's channel
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    /// Tools this one needed when it was installed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends: Vec<String>,
+}
+
+impl InstalledDb {
+    pub fn load(config: &Config) -> Result<Self> {
+        let path = config.state_path();
+
+        if !path.exists() {
+            return Ok(InstalledDb::default());
+        }
+
+        let json = fs::read_to_string(&path).context(format!(
+            "Failed to read installed-state database: {:?}",
+            path
+        ))?;
+        let db: InstalledDb = serde_json::from_str(&json).context(format!(
+            "Failed to parse installed-state database: {:?}",
+            path
+        ))?;
+
+        Ok(db)
+    }
+
+    pub fn save(&self, config: &Config) -> Result<()> {
+        let path = config.state_path();
+        let temp_path = path.with_extension("tmp");
+
+        // Write to a temporary file first so a crash never leaves a truncated database
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(&temp_path, json).context("Failed to write installed-state database")?;
+        fs::rename(&temp_path, &path).context("Failed to replace installed-state database")?;
+
+        Ok(())
+    }
+
+    pub fn get(&self, tool_name: &str) -> Option<&InstalledTool> {
+        self.tools.get(tool_name)
+    }
+
+    pub fn record(&mut self, tool_name: &str, tool: InstalledTool) {
+        self.tools.insert(tool_name.to_string(), tool);
+    }
+
+    pub fn forget(&mut self, tool_name: &str) -> Option<InstalledTool> {
+        self.tools.remove(tool_name)
+    }
+
+    /// The versions `tool_name` is pinned to, if it is pinned
+    pub fn pin(&self, tool_name: &str) -> Result<Option<Constraint>> {
+        self.pins
+            .get(tool_name)
+            .map(|pin| Constraint::parse(pin))
+            .transpose()
+            .context(format!(
+                "Invalid pin for '{}' in installed-state database",
+                tool_name
+            ))
+    }
+
+    /// Whether `path` is a link or directory owned by an installed package
+    pub fn owns_file(&self, path: &Path) -> bool {
+        self.tools
+            .values()
+            .any(|tool| tool.files.iter().any(|f| f == path))
+    }
+
+    /// Installed tools that were installed as needing `tool_name`
+    pub fn dependents(&self, tool_name: &str) -> Vec<&str> {
+        self.tools
+            .iter()
+            .filter(|(_, tool)| tool.depends.iter().any(|d| d == tool_name))
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// Names of the files in the install dir that gpm did not install
+    ///
+    /// These are never removed or overwritten without `--force`.
+    pub fn untracked(&self, config: &Config) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+
+        if !config.install_dir.exists() {
+            return Ok(names);
+        }
+
+        for entry in fs::read_dir(&config.install_dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+
+            // Dotfiles include gpm's own staging files, which are never tools
+            if name.starts_with('.')
+                || entry.file_type()?.is_dir()
+                || self.get(&name).is_some()
+                || self.owns_file(&path)
+            {
+                continue;
+            }
+            names.push(name);
+        }
+
+        names.sort();
+        Ok(names)
+    }
+}
+
+/// Records written before repositories existed all came from the default one
+fn default_repo() -> String {
+    DEFAULT_REPO.to_string()
+}
+
+pub fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(version: &str) -> InstalledTool {
+        serde_json::from_value(serde_json::json!({
+            "version": version,
+            "sha256": "0".repeat(64),
+            "url": "https://example.com/tool",
+            "installed_at": 0,
+            "repo_version": "1.0.0",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn reads_records_from_older_versions() {
+        let db: InstalledDb = serde_json::from_str(
+            r#"{"tools": {"make": {"version": "4.4.1", "sha256": "ab", "url": "u",
+                "installed_at": 1, "repo_version": "1.0.0"}}}"#,
+        )
+        .unwrap();
+
+        let make = db.get("make").unwrap();
+        assert_eq!(make.repo, DEFAULT_REPO);
+        assert_eq!(make.size, None);
+        assert!(make.files.is_empty() && make.depends.is_empty());
+        assert!(db.history.is_empty() && db.pins.is_empty());
+    }
+
+    #[test]
+    fn untracked_skips_tools_package_links_and_staging_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::for_tests(dir.path());
+        for name in ["make", "pk", "loose", ".make.gpm-new", ".pk.gpm-link"] {
+            fs::write(config.install_dir.join(name), "").unwrap();
+        }
+        fs::create_dir(config.install_dir.join("subdir")).unwrap();
+
+        let mut db = InstalledDb::default();
+        db.record("make", tool("4.4.1"));
+        let mut package = tool("1.0");
+        package.files = vec![config.install_dir.join("pk")];
+        db.record("package", package);
+
+        assert_eq!(db.untracked(&config).unwrap(), vec!["loose".to_string()]);
+    }
+}
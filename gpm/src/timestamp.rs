@@ -0,0 +1,157 @@
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Year, month and day of a number of days since 1970-01-01
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    (year_of_era + era * 400 + i64::from(month <= 2), month, day)
+}
+
+/// Seconds since the Unix epoch as `YYYY-MM-DD HH:MM UTC`
+pub fn format_unix(secs: u64) -> String {
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let time = secs % 86400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60
+    )
+}
+
+/// Seconds since the Unix epoch of an RFC 3339 timestamp such as `2026-01-31T12:00:00Z`
+pub fn parse(text: &str) -> Option<u64> {
+    let (date, time) = text.split_once(['T', 't', ' '])?;
+
+    let (clock, offset) = match time.strip_suffix(['Z', 'z']) {
+        Some(clock) => (clock, 0),
+        None => {
+            let (clock, zone) = time.split_at(time.rfind(['+', '-'])?);
+            let (hours, minutes) = zone[1..].split_once(':')?;
+            let offset = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+            (
+                clock,
+                if zone.starts_with('-') {
+                    -offset
+                } else {
+                    offset
+                },
+            )
+        }
+    };
+    // Fractional seconds do not matter here
+    let clock = clock.split('.').next()?;
+
+    let numbers = |text: &str, sep: char| -> Option<Vec<i64>> {
+        let parts: Vec<i64> = text
+            .split(sep)
+            .map(|part| part.parse().ok())
+            .collect::<Option<_>>()?;
+        (parts.len() == 3).then_some(parts)
+    };
+    let (year, month, day) = match numbers(date, '-')?[..] {
+        [year, month, day] => (year, month, day),
+        _ => return None,
+    };
+    let (hour, minute, second) = match numbers(clock, ':')?[..] {
+        [hour, minute, second] => (hour, minute, second),
+        _ => return None,
+    };
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || !(0..24).contains(&hour)
+        || !(0..60).contains(&minute)
+        || !(0..=60).contains(&second)
+    {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    u64::try_from(days * 86400 + hour * 3600 + minute * 60 + second - offset).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_dates() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(days_from_civil(1969, 12, 31), -1);
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+    }
+
+    #[test]
+    fn civil_round_trip_across_leap_years() {
+        // 1900 and 2100 are not leap years, 2000 is
+        for days in days_from_civil(1899, 1, 1)..days_from_civil(2101, 1, 1) {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(
+            civil_from_days(days_from_civil(1900, 2, 28) + 1),
+            (1900, 3, 1)
+        );
+        assert_eq!(
+            civil_from_days(days_from_civil(2000, 2, 28) + 1),
+            (2000, 2, 29)
+        );
+        assert_eq!(
+            civil_from_days(days_from_civil(2100, 2, 28) + 1),
+            (2100, 3, 1)
+        );
+    }
+
+    #[test]
+    fn parses_utc_and_offsets() {
+        assert_eq!(parse("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse("2024-02-29T12:30:15Z"), Some(1_709_209_815));
+        assert_eq!(parse("2024-02-29t12:30:15.250z"), Some(1_709_209_815));
+        assert_eq!(parse("2024-02-29T14:30:15+02:00"), Some(1_709_209_815));
+        assert_eq!(parse("2024-02-29T07:00:15-05:30"), Some(1_709_209_815));
+        assert_eq!(parse("2024-02-29 12:30:15+00:00"), Some(1_709_209_815));
+    }
+
+    #[test]
+    fn rejects_malformed_timestamps() {
+        assert_eq!(parse("2024-02-29"), None);
+        assert_eq!(parse("2024-13-01T00:00:00Z"), None);
+        assert_eq!(parse("2024-02-29T24:00:00Z"), None);
+        assert_eq!(parse("2024-02-29T12:00Z"), None);
+        assert_eq!(parse("2024-02-29T12:00:00+0200"), None);
+        // Before the epoch
+        assert_eq!(parse("1969-12-31T23:59:59Z"), None);
+    }
+
+    #[test]
+    fn format_round_trips_with_parse() {
+        assert_eq!(format_unix(0), "1970-01-01 00:00 UTC");
+        for text in ["2000-02-29T23:59:00Z", "2026-10-17T08:05:00Z"] {
+            let secs = parse(text).unwrap();
+            let formatted = format_unix(secs).replace(' ', "T").replace("TUTC", ":00Z");
+            assert_eq!(formatted, text);
+        }
+    }
+}
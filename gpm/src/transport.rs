@@ -0,0 +1,139 @@
+use anyhow::{Context, Result};
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use crate::config::Config;
+
+/// Turn a configured location into a URL
+///
+/// `http(s)://` and `file://` URLs are kept as they are; anything else is a
+/// local path, made absolute against the current directory.
+pub fn to_url(location: &str) -> Result<String> {
+    if location.contains("://") {
+        Url::parse(location).context(format!("Invalid URL: {}", location))?;
+        return Ok(location.to_string());
+    }
+
+    let path = std::path::absolute(location).context(format!("Invalid path: {}", location))?;
+    let url =
+        Url::from_file_path(&path).map_err(|_| anyhow::anyhow!("Invalid path: {}", location))?;
+
+    Ok(url.to_string())
+}
+
+/// URL of a repository's manifest; a directory means the `manifest.json` inside it
+pub fn manifest_url(location: &str) -> Result<String> {
+    let url = to_url(location)?;
+
+    match local_path(&url) {
+        Some(path) if path.is_dir() => Ok(format!("{}/manifest.json", url.trim_end_matches('/'))),
+        _ => Ok(url),
+    }
+}
+
+/// Resolve a URL from a manifest against the manifest's own URL
+///
+/// Lets a manifest on a USB stick or an exported mirror name its files relatively.
+pub fn join(base: &str, url: &str) -> String {
+    Url::parse(base)
+        .and_then(|base| base.join(url))
+        .map(|url| url.to_string())
+        .unwrap_or_else(|_| url.to_string())
+}
+
+/// The file a `file://` URL points at
+pub fn local_path(url: &str) -> Option<PathBuf> {
+    let url = Url::parse(url).ok()?;
+    if url.scheme() != "file" {
+        return None;
+    }
+    url.to_file_path().ok()
+}
+
+pub fn is_local(url: &str) -> bool {
+    local_path(url).is_some()
+}
+
+/// Validators a server sent with a file, to ask later whether it changed
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Validators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+/// Result of a conditional read
+pub enum Fetched {
+    Data(Vec<u8>, Validators),
+    NotModified,
+    Missing,
+}
+
+/// Read a whole file from the network or the local filesystem
+///
+/// Returns `None` when it does not exist.
+pub fn read(config: &Config, url: &str) -> Result<Option<Vec<u8>>> {
+    match read_if_changed(config, url, None)? {
+        Fetched::Data(data, _) => Ok(Some(data)),
+        Fetched::NotModified | Fetched::Missing => Ok(None),
+    }
+}
+
+/// Read a file unless the server says it still matches `validators`
+///
+/// Local files have no validators and are always read.
+pub fn read_if_changed(
+    config: &Config,
+    url: &str,
+    validators: Option<&Validators>,
+) -> Result<Fetched> {
+    if let Some(path) = local_path(url) {
+        return match fs::read(&path) {
+            Ok(data) => Ok(Fetched::Data(data, Validators::default())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Fetched::Missing),
+            Err(e) => Err(e).context(format!("Failed to read {:?}", path)),
+        };
+    }
+
+    let mut request = config.http()?.get(url);
+    if let Some(validators) = validators {
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let response = request.send().context(format!("Failed to fetch {}", url))?;
+
+    match response.status() {
+        StatusCode::NOT_FOUND => return Ok(Fetched::Missing),
+        StatusCode::NOT_MODIFIED if validators.is_some() => return Ok(Fetched::NotModified),
+        _ => {}
+    }
+
+    let response = response
+        .error_for_status()
+        .context(format!("{} returned error status", url))?;
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let validators = Validators {
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+    };
+    let data = response
+        .bytes()
+        .context(format!("Failed to read {}", url))?;
+
+    Ok(Fetched::Data(data.to_vec(), validators))
+}
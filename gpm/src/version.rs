@@ -0,0 +1,365 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::output::{fail, Kind};
+
+/// A version string ordered the way people read it: `4.4.1 < 4.10`, `1.0-rc1 < 1.0`
+///
+/// Digits compare as numbers and letters as text, so anything a build script
+/// writes (`v1.2`, `2024.01.15`, `9.5p1`) parses. Missing trailing numbers count
+/// as zero, which makes `4.4` and `4.4.0` equal. A suffix after `-` that starts
+/// with a letter is a pre-release, older than the release itself (`1.0-rc1`);
+/// one that starts with a digit or `r` and a digit is a packaging revision,
+/// newer than the release (`1.2-3`, `4.4.1-r1`). `+build` metadata is ignored.
+#[derive(Debug, Clone)]
+pub struct Version {
+    release: Vec<Part>,
+    pre: Vec<Part>,
+    revision: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Number(u64),
+    Text(String),
+}
+
+impl Version {
+    pub fn parse(version: &str) -> Version {
+        let version = version.trim();
+        let version = version.strip_prefix(['v', 'V']).unwrap_or(version);
+        let version = version.split_once('+').map_or(version, |(v, _)| v);
+        let (release, suffix) = version.split_once('-').unwrap_or((version, ""));
+
+        let revision = suffix
+            .strip_prefix(['r', 'R'])
+            .filter(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+            .or(Some(suffix).filter(|s| s.starts_with(|c: char| c.is_ascii_digit())));
+        let (pre, revision) = match revision {
+            Some(revision) => ("", revision),
+            None => (suffix, ""),
+        };
+
+        Version {
+            release: parts(release),
+            pre: parts(pre),
+            revision: parts(revision),
+        }
+    }
+
+    fn is_prerelease(&self) -> bool {
+        !self.pre.is_empty()
+    }
+
+    /// The smallest version above every version starting with the first `len` numbers of this one
+    fn bump(&self, len: usize) -> Version {
+        let mut release: Vec<Part> = self.release.iter().take(len).cloned().collect();
+        release.resize(len, Part::Number(0));
+        if let Some(Part::Number(n)) = release.last_mut() {
+            *n += 1;
+        }
+
+        Version {
+            release,
+            pre: Vec::new(),
+            revision: Vec::new(),
+        }
+    }
+}
+
+/// Runs of digits and of letters; `.`, `_` and other separators only split them
+fn parts(text: &str) -> Vec<Part> {
+    let mut parts = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_ascii_digit() {
+            let mut digits = String::new();
+            while let Some(&d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                digits.push(d);
+                chars.next();
+            }
+            parts.push(Part::Number(digits.parse().unwrap_or(u64::MAX)));
+        } else if c.is_alphabetic() {
+            let mut word = String::new();
+            while let Some(&l) = chars.peek().filter(|l| l.is_alphabetic()) {
+                word.push(l.to_ascii_lowercase());
+                chars.next();
+            }
+            parts.push(Part::Text(word));
+        } else {
+            chars.next();
+        }
+    }
+
+    parts
+}
+
+fn compare_parts(a: &[Part], b: &[Part]) -> Ordering {
+    for i in 0..a.len().max(b.len()) {
+        let ordering = match (a.get(i), b.get(i)) {
+            (Some(Part::Number(x)), Some(Part::Number(y))) => x.cmp(y),
+            (Some(Part::Text(x)), Some(Part::Text(y))) => x.cmp(y),
+            // `1.0.1` is newer than `1.0a`
+            (Some(Part::Number(_)), Some(Part::Text(_))) => Ordering::Greater,
+            (Some(Part::Text(_)), Some(Part::Number(_))) => Ordering::Less,
+            (Some(Part::Number(x)), None) => x.cmp(&0),
+            (None, Some(Part::Number(y))) => 0.cmp(y),
+            // `1.0a` is newer than `1.0`
+            (Some(Part::Text(_)), None) => Ordering::Greater,
+            (None, Some(Part::Text(_))) => Ordering::Less,
+            (None, None) => Ordering::Equal,
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    Ordering::Equal
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_parts(&self.release, &other.release)
+            .then_with(|| match (self.is_prerelease(), other.is_prerelease()) {
+                (false, false) => Ordering::Equal,
+                (true, false) => Ordering::Less,
+                (false, true) => Ordering::Greater,
+                (true, true) => compare_parts(&self.pre, &other.pre),
+            })
+            .then_with(|| compare_parts(&self.revision, &other.revision))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+/// Compare two version strings
+pub fn compare(a: &str, b: &str) -> Ordering {
+    Version::parse(a).cmp(&Version::parse(b))
+}
+
+/// Which versions of a tool are acceptable, as in `make@^4` or `gawk@>=5.1, <5.3`
+///
+/// A bare version selects exactly that version. `^4.2` allows anything up to
+/// the next change of the first non-zero number (`>=4.2, <5`), `~4.2` up to the
+/// next minor version (`>=4.2, <4.3`), and `*` any version. Comparisons
+/// separated by commas must all hold. Pre-releases only match ranges that
+/// mention a pre-release themselves.
+#[derive(Debug, Clone)]
+pub struct Constraint {
+    text: String,
+    bounds: Vec<(Op, Version)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Exact,
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+}
+
+impl Constraint {
+    pub fn parse(text: &str) -> anyhow::Result<Constraint> {
+        let mut bounds = Vec::new();
+
+        for term in text.split(',').map(str::trim) {
+            if term.is_empty() || term == "*" {
+                continue;
+            }
+
+            let (prefix, rest) = [">=", "<=", ">", "<", "=", "^", "~"]
+                .into_iter()
+                .find_map(|prefix| term.strip_prefix(prefix).map(|rest| (prefix, rest)))
+                .unwrap_or(("", term));
+            let version = Version::parse(rest);
+
+            if version.release.is_empty() {
+                fail!(
+                    Kind::Usage,
+                    "Invalid version constraint '{}': '{}' has no version number",
+                    text,
+                    term
+                );
+            }
+
+            let op = match prefix {
+                ">=" => Op::GreaterEq,
+                "<=" => Op::LessEq,
+                ">" => Op::Greater,
+                "<" => Op::Less,
+                "^" | "~" => {
+                    let numbers = version.release.len();
+                    let len = if prefix == "^" {
+                        // Up to and including the first non-zero number
+                        version
+                            .release
+                            .iter()
+                            .position(|p| *p != Part::Number(0))
+                            .map_or(numbers, |i| i + 1)
+                    } else {
+                        // Major and minor, or just the major if that is all there is
+                        numbers.min(2)
+                    };
+                    let upper = version.bump(len);
+                    bounds.push((Op::GreaterEq, version));
+                    bounds.push((Op::Less, upper));
+                    continue;
+                }
+                _ => Op::Exact,
+            };
+            bounds.push((op, version));
+        }
+
+        Ok(Constraint {
+            text: text.trim().to_string(),
+            bounds,
+        })
+    }
+
+    pub fn matches(&self, version: &str) -> bool {
+        let version = Version::parse(version);
+
+        let exact = self.bounds.iter().any(|(op, _)| *op == Op::Exact);
+        let mentions_pre = self
+            .bounds
+            .iter()
+            .any(|(op, v)| *op != Op::Less && v.is_prerelease());
+        if version.is_prerelease() && !exact && !mentions_pre {
+            return false;
+        }
+
+        self.bounds.iter().all(|(op, bound)| match op {
+            Op::Exact => version == *bound,
+            Op::Greater => version > *bound,
+            Op::GreaterEq => version >= *bound,
+            Op::Less => version < *bound,
+            Op::LessEq => version <= *bound,
+        })
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(constraint: &str, version: &str) -> bool {
+        Constraint::parse(constraint).unwrap().matches(version)
+    }
+
+    #[test]
+    fn orders_versions_the_way_people_read_them() {
+        let ascending = [
+            "unknown",
+            "0.9",
+            "1.0-alpha",
+            "1.0-rc1",
+            "1.0-rc2",
+            "1.0",
+            "1.0-r1",
+            "1.0-2",
+            "1.0a",
+            "1.0.1",
+            "1.2",
+            "4.4.1",
+            "4.10",
+            "9.5p1",
+            "2024.01.15",
+        ];
+        for pair in ascending.windows(2) {
+            assert_eq!(compare(pair[0], pair[1]), Ordering::Less, "{:?}", pair);
+        }
+
+        assert_eq!(compare("4.4", "4.4.0"), Ordering::Equal);
+        assert_eq!(compare("v1.2", "1.2"), Ordering::Equal);
+        assert_eq!(compare("1.2+build.5", "1.2"), Ordering::Equal);
+        assert_eq!(compare("4.4.1-r0", "4.4.1"), Ordering::Equal);
+    }
+
+    #[test]
+    fn only_a_suffix_starting_with_a_letter_is_a_prerelease() {
+        for pre in ["1.0-rc1", "1.0-beta.2", "1.0-alpha", "2.0-dev"] {
+            assert!(Version::parse(pre).is_prerelease(), "{}", pre);
+        }
+        for release in ["4.4.1-r0", "4.4.1-r12", "1.2-3", "1.2-3.1", "1.2"] {
+            assert!(!Version::parse(release).is_prerelease(), "{}", release);
+        }
+
+        assert!(Version::parse("4.4.1-r2") > Version::parse("4.4.1-r1"));
+        assert!(Version::parse("4.4.1-r9") < Version::parse("4.4.2"));
+        assert!(Version::parse("1.2-10") > Version::parse("1.2-9"));
+    }
+
+    #[test]
+    fn caret_allows_changes_below_the_first_nonzero_number() {
+        assert!(matches("^4.2", "4.2"));
+        assert!(matches("^4.2", "4.9.1"));
+        assert!(!matches("^4.2", "4.1"));
+        assert!(!matches("^4.2", "5.0"));
+
+        assert!(matches("^0.3", "0.3.7"));
+        assert!(!matches("^0.3", "0.4"));
+        assert!(matches("^0.0.3", "0.0.3-r1"));
+        assert!(!matches("^0.0.3", "0.0.4"));
+    }
+
+    #[test]
+    fn tilde_allows_changes_below_the_minor_version() {
+        assert!(matches("~4.2", "4.2.9"));
+        assert!(!matches("~4.2", "4.3"));
+        assert!(matches("~4.2.1", "4.2.5"));
+        assert!(!matches("~4.2.1", "4.2.0"));
+        assert!(matches("~4", "4.9"));
+        assert!(!matches("~4", "5.0"));
+    }
+
+    #[test]
+    fn comparisons_must_all_hold() {
+        assert!(matches(">=5.1, <5.3", "5.1"));
+        assert!(matches(">=5.1, <5.3", "5.2.9"));
+        assert!(!matches(">=5.1, <5.3", "5.3"));
+        assert!(!matches(">=5.1, <5.3", "5.0.9"));
+        assert!(matches(">1.0, <=2", "2.0"));
+        assert!(!matches(">1.0", "1.0"));
+        assert!(matches("*", "0.1"));
+        assert!(matches("1.2", "1.2.0"));
+        assert!(!matches("1.2", "1.2.1"));
+    }
+
+    #[test]
+    fn prereleases_only_match_ranges_that_mention_one() {
+        assert!(!matches(">=1.0", "2.0-rc1"));
+        assert!(!matches("^1", "1.5-beta"));
+        assert!(!matches("<2.0", "2.0-rc1"));
+        assert!(matches(">=2.0-rc1", "2.0-rc2"));
+        assert!(matches("2.0-rc1", "2.0-rc1"));
+        // A packaging revision is a release
+        assert!(matches(">=4.4", "4.4.1-r0"));
+    }
+
+    #[test]
+    fn rejects_constraints_without_a_version_number() {
+        assert!(Constraint::parse(">=").is_err());
+        assert!(Constraint::parse("^").is_err());
+        assert!(Constraint::parse("").unwrap().matches("1.0"));
+    }
+}
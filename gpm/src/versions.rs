@@ -0,0 +1,109 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::state::InstalledDb;
+
+/// Copy of a previously installed version, kept in the artifact cache by sha256
+pub fn stored_path(config: &Config, sha256: &str) -> PathBuf {
+    config.artifact_path(sha256)
+}
+
+/// Make sure the current version is in the artifact cache and record it in history
+///
+/// `incoming_sha256` is the version about to replace it, which leaves history
+/// as it becomes current.
+pub fn archive_current(
+    config: &Config,
+    db: &mut InstalledDb,
+    tool_name: &str,
+    incoming_sha256: &str,
+) -> Result<()> {
+    let Some(current) = db.get(tool_name).cloned() else {
+        return Ok(());
+    };
+
+    let stored = stored_path(config, &current.sha256);
+
+    if !stored.exists() {
+        // Packages are only ever unpacked from the cache, so a missing archive cannot be rebuilt
+        let installed = config.install_dir.join(tool_name);
+        if current.archive.is_some() || !installed.exists() {
+            return Ok(());
+        }
+
+        fs::create_dir_all(config.artifacts_dir()).context("Failed to create artifact cache")?;
+        let temp = stored.with_extension("tmp");
+        fs::copy(&installed, &temp).context("Failed to archive previous version")?;
+        fs::rename(&temp, &stored).context("Failed to archive previous version")?;
+    }
+
+    let history = db.history.entry(tool_name.to_string()).or_default();
+    history.retain(|t| t.sha256 != current.sha256 && t.sha256 != incoming_sha256);
+    history.push(current);
+
+    prune(config, db, tool_name)
+}
+
+/// Drop the oldest archived versions beyond the `keep_versions` setting
+fn prune(config: &Config, db: &mut InstalledDb, tool_name: &str) -> Result<()> {
+    let Some(history) = db.history.get_mut(tool_name) else {
+        return Ok(());
+    };
+
+    let mut dropped = Vec::new();
+    while history.len() > config.keep_versions {
+        dropped.push(history.remove(0).sha256);
+    }
+
+    for sha256 in dropped {
+        release(config, db, &sha256)?;
+    }
+
+    Ok(())
+}
+
+/// Forget the archived versions of a removed tool
+///
+/// Their artifacts stay in the cache, so reinstalling the tool needs no network.
+pub fn forget(db: &mut InstalledDb, tool_name: &str) {
+    db.history.remove(tool_name);
+}
+
+/// Remove a cached artifact once no installed tool or history entry refers to it
+fn release(config: &Config, db: &InstalledDb, sha256: &str) -> Result<()> {
+    let in_use = db.tools.values().any(|t| t.sha256 == sha256)
+        || db.history.values().flatten().any(|t| t.sha256 == sha256);
+
+    let path = stored_path(config, sha256);
+    if !in_use && path.exists() {
+        fs::remove_file(&path).context("Failed to remove cached artifact")?;
+    }
+
+    Ok(())
+}
+
+/// Atomically replace `dest` with the verified file at `staged`
+///
+/// `staged` must live in the same directory as `dest` so the rename cannot
+/// cross filesystems.
+pub fn swap_into_place(staged: &Path, dest: &Path) -> Result<()> {
+    let mut permissions = fs::metadata(staged)?.permissions();
+    permissions.set_mode(0o755);
+    fs::set_permissions(staged, permissions)?;
+
+    fs::rename(staged, dest).context("Failed to move file to final destination")?;
+
+    Ok(())
+}
+
+/// Path next to `dest` used to stage a replacement before swapping it in
+pub fn staging_path(dest: &Path) -> PathBuf {
+    let name = dest
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    dest.with_file_name(format!(".{}.gpm-new", name))
+}
@@ -5,8 +5,36 @@ generate-manifest.py - Generate manifest.json for binary distribution
 import hashlib
 import json
 import os
+import shutil
 import sys
-from datetime import datetime
+import time
+from datetime import datetime, timedelta
+
+DEFAULT_TARGET = "x86_64-unknown-linux-musl"
+EXTRA_TARGETS = [
+    "aarch64-unknown-linux-musl",
+    "armv7-unknown-linux-musleabihf",
+]
+
+# gpm refuses a manifest after it expires, so releases must be re-signed before then
+DEFAULT_VALIDITY_DAYS = 90
+
+# Older releases kept per tool when PREVIOUS_MANIFEST is set
+DEFAULT_HISTORY = 5
+
+STABLE_CHANNEL = "stable"
+
+# Fields of a tool entry that describe one release
+RELEASE_FIELDS = [
+    "version",
+    "url",
+    "sha256",
+    "mirrors",
+    "size",
+    "source_url",
+    "source_sha256",
+    "targets",
+]
 
 
 def read_version(tool_name, repo_version="1.0.0"):
@@ -45,6 +73,60 @@ def load_tools_metadata(repo):
     return metadata
 
 
+def load_previous_manifest():
+    """Load the manifest this one replaces, if PREVIOUS_MANIFEST names one"""
+    path = os.environ.get("PREVIOUS_MANIFEST")
+    if not path:
+        return {}
+    if not os.path.exists(path):
+        print(f"Warning: {path} not found, publishing without history")
+        return {}
+    with open(path, "r") as f:
+        return json.load(f).get("tools", {})
+
+
+def carry_history(tool_entry, previous, published, limit):
+    """Keep earlier releases of a tool installable with gpm install name@version"""
+    if not previous or limit <= 0:
+        return
+
+    candidates = [{k: previous[k] for k in RELEASE_FIELDS if k in previous}]
+    candidates += previous.get("history", [])
+
+    history = []
+    for release in candidates:
+        urls = [release.get("url", "")]
+        urls += [t.get("url", "") for t in release.get("targets", {}).values()]
+        # Files this upload overwrites no longer match the release's checksums
+        if release["version"] == tool_entry["version"] or any(u in published for u in urls):
+            continue
+        if any(r["version"] == release["version"] for r in history):
+            continue
+        history.append(release)
+
+    if history:
+        tool_entry["history"] = history[:limit]
+
+
+def publish_versioned(tool_name, version, suffix=""):
+    """Copy a release file to a name with its version, which later uploads never replace"""
+    versioned = f"{tool_name}-{version}{suffix}"
+    shutil.copy2(f"release-files/{tool_name}{suffix}", f"release-files/{versioned}")
+    return versioned
+
+
+def publish_on_channel(tool_name, tool_entry, previous, channel):
+    """Offer a build to subscribers of channel, keeping the stable release as it is"""
+    release = {k: tool_entry[k] for k in RELEASE_FIELDS if k in tool_entry}
+    if not previous:
+        print(f"Note: {tool_name} has no stable release yet, publishing it as stable")
+        return tool_entry
+
+    entry = json.loads(json.dumps(previous))
+    entry.setdefault("channels", {})[channel] = release
+    return entry
+
+
 def main():
     if len(sys.argv) < 3:
         print("Usage: generate-manifest.py <repo> <release_tag> [repo_version]")
@@ -56,11 +138,27 @@ def main():
 
     tools_metadata = load_tools_metadata(repo)
 
+    now = datetime.utcnow()
+    validity_days = int(os.environ.get("MANIFEST_VALIDITY_DAYS", DEFAULT_VALIDITY_DAYS))
+    history_limit = int(os.environ.get("MANIFEST_HISTORY", DEFAULT_HISTORY))
+    previous_tools = load_previous_manifest()
+    channel = os.environ.get("MANIFEST_CHANNEL", STABLE_CHANNEL)
+    if channel != STABLE_CHANNEL and not previous_tools:
+        print("Error: MANIFEST_CHANNEL needs PREVIOUS_MANIFEST to keep the stable releases")
+        sys.exit(1)
+
     manifest = {
         "repo_version": repo_version,
-        "updated_at": datetime.utcnow().strftime("%Y-%m-%dT%H:%M:%SZ"),
-        "tools": {},
+        "updated_at": now.strftime("%Y-%m-%dT%H:%M:%SZ"),
+        # Generation time only ever grows, which is all gpm needs to spot an older manifest
+        "serial": int(time.time()),
+        # A channel build only adds to the published manifest
+        "tools": dict(previous_tools) if channel != STABLE_CHANNEL else {},
     }
+    if validity_days > 0:
+        manifest["expires"] = (now + timedelta(days=validity_days)).strftime(
+            "%Y-%m-%dT%H:%M:%SZ"
+        )
 
     if not os.path.exists("release-files"):
         print("Error: release-files directory not found")
@@ -69,6 +167,8 @@ def main():
     for tool_name in os.listdir("release-files"):
         if tool_name.startswith("."):
             continue
+        if any(tool_name.endswith(f"-{target}") for target in EXTRA_TARGETS):
+            continue
 
         filepath = f"release-files/{tool_name}"
         if not os.path.isfile(filepath):
@@ -86,23 +186,59 @@ def main():
         metadata = tools_metadata[tool_name]
         tool_version = read_version(tool_name, repo_version)
 
+        # The manifest points at versioned copies, so the URLs of earlier releases
+        # stay valid when the next build is uploaded under the same tag
+        download = f"https://github.com/{repo}/releases/download/{release_tag}/"
+        url = download + publish_versioned(tool_name, tool_version)
+        published = {download + tool_name, url}
         tool_entry = {
             "version": tool_version,
             "description": metadata["description"],
-            "url": f"https://github.com/{repo}/releases/download/{release_tag}/{tool_name}",
+            # Legacy top-level fields describe the x86_64 musl build
+            "url": url,
             "sha256": sha256,
             "size": size,
             "build_type": metadata["build_type"],
             "license": metadata["license"],
             "source_url": metadata["source_url"],
+            "targets": {
+                DEFAULT_TARGET: {"url": url, "sha256": sha256, "size": size},
+            },
         }
 
+        # Additional architectures are published as <tool>-<target triple>
+        for target in EXTRA_TARGETS:
+            target_path = f"release-files/{tool_name}-{target}"
+            if not os.path.isfile(target_path):
+                continue
+            with open(target_path, "rb") as f:
+                target_data = f.read()
+            target_url = download + publish_versioned(tool_name, tool_version, f"-{target}")
+            published |= {f"{download}{tool_name}-{target}", target_url}
+            tool_entry["targets"][target] = {
+                "url": target_url,
+                "sha256": hashlib.sha256(target_data).hexdigest(),
+                "size": len(target_data),
+            }
+
         if "source_sha256" in metadata:
             tool_entry["source_sha256"] = metadata["source_sha256"]
+        for relation in ("depends", "recommends"):
+            if metadata.get(relation):
+                tool_entry[relation] = metadata[relation]
+
+        previous = previous_tools.get(tool_name)
+        if channel != STABLE_CHANNEL:
+            tool_entry = publish_on_channel(tool_name, tool_entry, previous, channel)
+        else:
+            carry_history(tool_entry, previous, published, history_limit)
+            # Channel builds stay offered until a newer stable release makes them moot
+            if previous and "channels" in previous:
+                tool_entry["channels"] = previous["channels"]
 
         manifest["tools"][tool_name] = tool_entry
         print(
-            f"Added {tool_name}: {size} bytes, SHA256: {sha256[:16]}..., Version: {tool_version}"
+            f"Added {tool_name} ({channel}): {size} bytes, SHA256: {sha256[:16]}..., Version: {tool_version}"
         )
 
     with open("release-files/manifest.json", "w") as f:
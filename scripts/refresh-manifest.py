@@ -0,0 +1,46 @@
+#!/usr/bin/env python3
+"""
+refresh-manifest.py - Renew the serial and expiry of a published manifest
+"""
+import json
+import os
+import sys
+import time
+from datetime import datetime, timedelta
+
+# Keep in step with generate-manifest.py
+DEFAULT_VALIDITY_DAYS = 90
+
+
+def main():
+    if len(sys.argv) < 2:
+        print("Usage: refresh-manifest.py <manifest.json>")
+        sys.exit(1)
+
+    path = sys.argv[1]
+    with open(path, "r") as f:
+        manifest = json.load(f)
+
+    now = datetime.utcnow()
+    validity_days = int(os.environ.get("MANIFEST_VALIDITY_DAYS", DEFAULT_VALIDITY_DAYS))
+
+    # The tools stay exactly as published; only the freshness fields move on
+    manifest["updated_at"] = now.strftime("%Y-%m-%dT%H:%M:%SZ")
+    manifest["serial"] = max(int(time.time()), manifest.get("serial", 0) + 1)
+    if validity_days > 0:
+        manifest["expires"] = (now + timedelta(days=validity_days)).strftime(
+            "%Y-%m-%dT%H:%M:%SZ"
+        )
+    else:
+        manifest.pop("expires", None)
+
+    with open(path, "w") as f:
+        json.dump(manifest, f, indent=2)
+
+    print(
+        f"Refreshed {path}: serial {manifest['serial']}, expires {manifest.get('expires', 'never')}"
+    )
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,29 @@
+#!/bin/bash
+# sign-manifest.sh - Sign a manifest with the minisign key in MINISIGN_SECRET_KEY
+set -euo pipefail
+
+MANIFEST_PATH="${1-}"
+REPO_VERSION="${2-}"
+
+if [[ -z ${MANIFEST_PATH} ]] || [[ -z ${REPO_VERSION} ]]; then
+	echo "Usage: $0 <manifest_path> <repo_version>"
+	exit 1
+fi
+
+if [[ -z ${MINISIGN_SECRET_KEY-} ]]; then
+	echo "Error: MINISIGN_SECRET_KEY secret is not set; gpm refuses unsigned manifests"
+	exit 1
+fi
+
+if ! command -v minisign >/dev/null; then
+	sudo apt-get install -y minisign
+fi
+
+umask 077
+printf '%s\n' "${MINISIGN_SECRET_KEY}" >minisign.key
+trap 'rm -f minisign.key' EXIT
+minisign -S -s minisign.key \
+	-m "${MANIFEST_PATH}" \
+	-x "${MANIFEST_PATH}.minisig" \
+	-t "glochidia manifest ${REPO_VERSION} ($(date -u +%Y-%m-%dT%H:%M:%SZ))" </dev/null
+cat "${MANIFEST_PATH}.minisig"